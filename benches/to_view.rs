@@ -0,0 +1,128 @@
+use std::time::{Duration, Instant};
+
+use monitor_table::{
+    row::{FieldVisitor, LiteralType, LiteralValue, TableRow, ValueDisplay},
+    table::Table,
+};
+
+const ROWS: usize = 100_000;
+const ITERATIONS: u32 = 10;
+
+struct Row {
+    id: u64,
+    name: String,
+    cpu: f64,
+    mem: u64,
+    state: String,
+    parent: u64,
+    threads: i64,
+    running: bool,
+}
+impl TableRow for Row {
+    fn schema() -> Vec<(String, LiteralType)> {
+        vec![
+            ("id".to_string(), LiteralType::UInt),
+            ("name".to_string(), LiteralType::String),
+            ("cpu".to_string(), LiteralType::Float),
+            ("mem".to_string(), LiteralType::UInt),
+            ("state".to_string(), LiteralType::String),
+            ("parent".to_string(), LiteralType::UInt),
+            ("threads".to_string(), LiteralType::Int),
+            ("running".to_string(), LiteralType::Bool),
+        ]
+    }
+
+    fn fields(&self) -> Vec<Option<LiteralValue>> {
+        vec![
+            Some(self.id.into()),
+            Some(self.name.clone().into()),
+            Some(self.cpu.into()),
+            Some(self.mem.into()),
+            Some(self.state.clone().into()),
+            Some(self.parent.into()),
+            Some(self.threads.into()),
+            Some(self.running.into()),
+        ]
+    }
+
+    fn visit_fields(&self, visitor: &mut dyn FieldVisitor) {
+        visitor.visit_uint(0, self.id);
+        visitor.visit_str(1, &self.name);
+        visitor.visit_float(2, self.cpu);
+        visitor.visit_uint(3, self.mem);
+        visitor.visit_str(4, &self.state);
+        visitor.visit_uint(5, self.parent);
+        visitor.visit_int(6, self.threads);
+        visitor.visit_bool(7, self.running);
+    }
+}
+impl ValueDisplay for Row {}
+
+/// A row that only builds its values through `fields()`
+struct FieldsRow(Row);
+impl TableRow for FieldsRow {
+    fn schema() -> Vec<(String, LiteralType)> {
+        Row::schema()
+    }
+
+    fn fields(&self) -> Vec<Option<LiteralValue>> {
+        self.0.fields()
+    }
+}
+impl ValueDisplay for FieldsRow {}
+
+fn row(i: usize) -> Row {
+    Row {
+        id: i as u64,
+        name: format!("process-{i}"),
+        cpu: (i % 100) as f64 / 3.0,
+        mem: (i * 4096) as u64,
+        state: ["running", "sleeping", "zombie"][i % 3].to_string(),
+        parent: (i / 10) as u64,
+        threads: (i % 16) as i64,
+        running: i.is_multiple_of(3),
+    }
+}
+
+fn main() {
+    println!("visit_fields:");
+    let table = Table::new();
+    let _guards = (0..ROWS)
+        .map(|i| table.set_scope(row(i)))
+        .collect::<Vec<_>>();
+    run(&table);
+
+    println!("fields:");
+    let table = Table::new();
+    let _guards = (0..ROWS)
+        .map(|i| table.set_scope(FieldsRow(row(i))))
+        .collect::<Vec<_>>();
+    run(&table);
+}
+
+fn run<R: TableRow + ValueDisplay>(table: &Table<R>) {
+    for sql in [
+        "",
+        "filter id = 42",
+        "filter cpu > 30",
+        "select id cpu",
+        "filter running = true\nselect name",
+    ] {
+        if let Err(e) = table.to_view(sql) {
+            println!("{sql:?}: {e}");
+            continue;
+        }
+        let elapsed = bench(|| {
+            table.to_view(sql).unwrap();
+        });
+        println!("{sql:?}: {elapsed:?}");
+    }
+}
+
+fn bench(mut f: impl FnMut()) -> Duration {
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        f();
+    }
+    start.elapsed() / ITERATIONS
+}
@@ -0,0 +1,142 @@
+use core::fmt;
+use std::{
+    collections::HashMap,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use dfsql::backend::DynamicExecutor;
+
+use crate::{options::ViewOptions, table_view::en::TableViewWrite};
+
+/// Distinct queries kept at most so that ad hoc queries do not grow the cache forever.
+const CAPACITY: usize = 64;
+
+/// A parsed dfsql statement ready to run on an executor.
+pub(crate) type Statement = Arc<dyn Fn(&mut DynamicExecutor) -> anyhow::Result<()> + Send + Sync>;
+
+pub(crate) fn parse(sql: &str) -> anyhow::Result<Statement> {
+    let sql = dfsql::sql::parse(sql)?;
+    Ok(Arc::new(move |executor: &mut DynamicExecutor| {
+        executor.execute(&sql)?;
+        Ok(())
+    }))
+}
+
+/// Parsed statements and rendered views keyed by the SQL text.
+///
+/// A view is reused while the table stays at the generation it was rendered at, or while it is younger than the max staleness.
+/// Views with time-dependent meta columns are only reused within the max staleness.
+#[derive(Default)]
+pub(crate) struct QueryCache {
+    /// `None` disables the cache.
+    max_staleness: Option<Duration>,
+    entries: HashMap<String, Entry>,
+}
+impl QueryCache {
+    pub fn set_max_staleness(&mut self, max_staleness: Option<Duration>) {
+        self.max_staleness = max_staleness;
+        if max_staleness.is_none() {
+            self.entries.clear();
+        }
+    }
+
+    pub fn view(
+        &mut self,
+        sql: &str,
+        generation: u64,
+        options: &ViewOptions,
+    ) -> Option<TableViewWrite> {
+        let max_staleness = self.max_staleness?;
+        let entry = self.entries.get_mut(sql)?;
+        entry.last_used = Instant::now();
+        let cached = entry.view.as_ref()?;
+        if cached.options != *options {
+            return None;
+        }
+        let time_dependent = options.meta_columns.iter().any(|m| m.is_time_dependent());
+        let fresh = (cached.generation == generation && !time_dependent)
+            || cached.rendered_at.elapsed() <= max_staleness;
+        fresh.then(|| cached.view.clone())
+    }
+
+    pub fn statement(&mut self, sql: &str) -> anyhow::Result<Statement> {
+        if self.max_staleness.is_none() {
+            return parse(sql);
+        }
+        if let Some(entry) = self.entries.get_mut(sql) {
+            entry.last_used = Instant::now();
+            return Ok(entry.statement.clone());
+        }
+        let statement = parse(sql)?;
+        self.make_room();
+        self.entries.insert(
+            sql.to_string(),
+            Entry {
+                statement: statement.clone(),
+                view: None,
+                last_used: Instant::now(),
+            },
+        );
+        Ok(statement)
+    }
+
+    pub fn insert_view(
+        &mut self,
+        sql: &str,
+        generation: u64,
+        options: &ViewOptions,
+        view: TableViewWrite,
+    ) {
+        let Some(entry) = self.entries.get_mut(sql) else {
+            return;
+        };
+        // Keep the view of the newer generation if another query finished first
+        if let Some(cached) = &entry.view
+            && generation < cached.generation
+        {
+            return;
+        }
+        entry.view = Some(CachedView {
+            generation,
+            rendered_at: Instant::now(),
+            options: options.clone(),
+            view,
+        });
+    }
+
+    fn make_room(&mut self) {
+        if self.entries.len() < CAPACITY {
+            return;
+        }
+        let lru = self
+            .entries
+            .iter()
+            .min_by_key(|(_, entry)| entry.last_used)
+            .map(|(sql, _)| sql.clone());
+        if let Some(sql) = lru {
+            self.entries.remove(&sql);
+        }
+    }
+}
+impl fmt::Debug for QueryCache {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("QueryCache")
+            .field("max_staleness", &self.max_staleness)
+            .field("queries", &self.entries.len())
+            .finish()
+    }
+}
+
+struct Entry {
+    statement: Statement,
+    view: Option<CachedView>,
+    last_used: Instant,
+}
+
+struct CachedView {
+    generation: u64,
+    rendered_at: Instant,
+    options: ViewOptions,
+    view: TableViewWrite,
+}
@@ -0,0 +1,87 @@
+use core::fmt;
+use std::collections::BTreeMap;
+
+use crate::{
+    row::{LiteralType, LiteralValue, TableRow, ValueDisplay},
+    table::RowKey,
+};
+
+/// Upper bound on the rows of a [`crate::table::Table`], set by [`crate::table::Table::set_capacity`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Capacity {
+    pub max_rows: usize,
+    pub policy: CapacityPolicy,
+}
+impl Capacity {
+    pub fn new(max_rows: usize, policy: CapacityPolicy) -> Self {
+        Self { max_rows, policy }
+    }
+}
+
+/// Which row [`crate::table::Table::insert`] evicts from a full table.
+///
+/// [`crate::table::Table::try_insert`] rejects the new row instead.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CapacityPolicy {
+    /// Remove the row inserted first.
+    EvictOldest,
+    /// Remove the row inserted or mutated least recently.
+    EvictLeastRecentlyUpdated,
+}
+
+/// A row was rejected because the table is full.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CapacityError {
+    pub max_rows: usize,
+}
+impl fmt::Display for CapacityError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Table is full at {} rows", self.max_rows)
+    }
+}
+impl std::error::Error for CapacityError {}
+
+/// Size and eviction counters of a table.
+///
+/// It is a row itself so that it can be queried with [`crate::table::Table::stats_view`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TableStats {
+    pub rows: usize,
+    pub max_rows: Option<usize>,
+    /// Rows removed to make room for new ones.
+    pub evicted: u64,
+    /// Rows refused by [`crate::table::Table::try_insert`].
+    pub rejected: u64,
+}
+impl TableRow for TableStats {
+    fn schema() -> Vec<(String, LiteralType)> {
+        vec![
+            ("rows".to_string(), LiteralType::UInt),
+            ("max_rows".to_string(), LiteralType::UInt),
+            ("evicted".to_string(), LiteralType::UInt),
+            ("rejected".to_string(), LiteralType::UInt),
+        ]
+    }
+
+    fn fields(&self) -> Vec<Option<LiteralValue>> {
+        vec![
+            Some((self.rows as u64).into()),
+            self.max_rows.map(|n| (n as u64).into()),
+            Some(self.evicted.into()),
+            Some(self.rejected.into()),
+        ]
+    }
+}
+impl ValueDisplay for TableStats {}
+
+/// Capacity of a table and what it has done so far.
+#[derive(Debug, Default)]
+pub(crate) struct Limits {
+    pub capacity: Option<Capacity>,
+    pub evicted: u64,
+    pub rejected: u64,
+    /// Keys of the rows by the generation the [`CapacityPolicy`] orders them by, next victim first.
+    ///
+    /// It is only kept while there is a capacity.
+    pub order: BTreeMap<u64, RowKey>,
+}
@@ -0,0 +1,138 @@
+use core::ops::Range;
+
+use crate::{
+    lex::{KEYWORDS, STATEMENTS, TokenKind, lex},
+    row::LiteralType,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Completion {
+    /// Text to put in place of `replace`, quoted if it is a column name that is not a plain identifier or is a dfsql word.
+    pub text: String,
+    pub kind: CompletionKind,
+    /// Byte range of the partial word before the cursor.
+    pub replace: Range<usize>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompletionKind {
+    Statement,
+    Keyword,
+    Column,
+}
+
+/// Suggest words that can replace the partial word ending at byte `cursor` of `sql`.
+///
+/// Statements are suggested at the start of a line; columns of `schema` and then other keywords elsewhere.
+pub fn complete(sql: &str, cursor: usize, schema: &[(String, LiteralType)]) -> Vec<Completion> {
+    if !sql.is_char_boundary(cursor) {
+        return vec![];
+    }
+    let before = &sql[..cursor];
+    let start = before
+        .char_indices()
+        .rev()
+        .take_while(|(_, c)| is_ident_char(*c))
+        .last()
+        .map(|(i, _)| i)
+        .unwrap_or(cursor);
+    let prefix = &sql[start..cursor];
+    let line_start = before[..start].rfind('\n').map(|i| i + 1).unwrap_or(0);
+    let line = lex(&before[line_start..start]);
+
+    let completion = |text: String, kind| Completion {
+        text,
+        kind,
+        replace: start..cursor,
+    };
+    let Some(first) = line.first() else {
+        return STATEMENTS
+            .iter()
+            .filter(|s| s.starts_with(prefix))
+            .map(|s| completion(s.to_string(), CompletionKind::Statement))
+            .collect();
+    };
+    if first.kind == TokenKind::Ident("limit") {
+        return vec![];
+    }
+    let columns = schema
+        .iter()
+        .filter(|(name, _)| name.starts_with(prefix))
+        .map(|(name, _)| completion(quote(name), CompletionKind::Column));
+    // Keywords would drown the columns without a prefix to narrow them down
+    let keywords = KEYWORDS
+        .iter()
+        .filter(|k| !prefix.is_empty() && k.starts_with(prefix))
+        .map(|k| completion(k.to_string(), CompletionKind::Keyword));
+    columns.chain(keywords).collect()
+}
+
+fn is_ident_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+/// Quote `name` as a dfsql string unless it reads as a plain identifier.
+///
+/// dfsql strings only escape `"` and `\` with a backslash.
+fn quote(name: &str) -> String {
+    let plain = name.chars().all(is_ident_char)
+        && name.chars().next().is_some_and(|c| !c.is_ascii_digit())
+        && !STATEMENTS.contains(&name)
+        && !KEYWORDS.contains(&name);
+    if plain {
+        return name.to_string();
+    }
+    let mut quoted = String::from('"');
+    for c in name.chars() {
+        if matches!(c, '"' | '\\') {
+            quoted.push('\\');
+        }
+        quoted.push(c);
+    }
+    quoted.push('"');
+    quoted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn texts(completions: Vec<Completion>) -> Vec<String> {
+        completions.into_iter().map(|c| c.text).collect()
+    }
+
+    #[test]
+    fn test_complete() {
+        let schema = [
+            ("cpu_usage".to_string(), LiteralType::Float),
+            ("mem".to_string(), LiteralType::UInt),
+            ("conn-id".to_string(), LiteralType::String),
+        ];
+
+        assert_eq!(texts(complete("so", 2, &schema)), ["sort"]);
+        assert_eq!(texts(complete("sort x\n", 7, &schema)), STATEMENTS);
+        let completions = complete("sort mem\nfilter c > 1", 17, &schema);
+        assert_eq!(
+            texts(completions.clone()),
+            ["cpu_usage", "\"conn-id\"", "col", "count", "cast"]
+        );
+        assert_eq!(completions[0].replace, 16..17);
+        assert_eq!(completions[0].kind, CompletionKind::Column);
+        assert_eq!(texts(complete("filter ", 7, &schema)).len(), 3);
+        assert!(complete("limit ", 6, &schema).is_empty());
+    }
+
+    #[test]
+    fn test_quote() {
+        assert_eq!(quote("mem"), "mem");
+        assert_eq!(quote("count"), "\"count\"");
+        assert_eq!(quote("1st"), "\"1st\"");
+        assert_eq!(quote(r#"a"b\c"#), r#""a\"b\\c""#);
+        for name in ["conn-id", "count", r#"a"b\c"#, "µs/req"] {
+            let quoted = quote(name);
+            let tokens = lex(&quoted);
+            assert_eq!(tokens.len(), 1);
+            assert_eq!(tokens[0].kind, TokenKind::String(name.to_string()));
+        }
+    }
+}
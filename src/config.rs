@@ -0,0 +1,115 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::Path,
+};
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+
+use crate::options::{NamedView, ViewOptions};
+
+/// Display settings of the tables of an application, keyed by table name.
+///
+/// ```toml
+/// [tables.conns]
+/// default_query = "sort cpu desc"
+/// column_order = ["name"]
+/// hidden_columns = ["pid"]
+///
+/// [tables.conns.column_formats]
+/// mem = "bytes_iec"
+/// cpu = { percent = { precision = 1 } }
+///
+/// [tables.conns.alignments]
+/// name = "right"
+///
+/// [tables.conns.views.top-cpu]
+/// sql = "sort cpu desc\nlimit 10"
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub tables: HashMap<String, TableConfig>,
+}
+impl Config {
+    pub fn from_toml(s: &str) -> anyhow::Result<Self> {
+        toml::from_str(s).context("Failed to parse the TOML config")
+    }
+
+    pub fn from_json(s: &str) -> anyhow::Result<Self> {
+        serde_json::from_str(s).context("Failed to parse the JSON config")
+    }
+
+    /// Read a `.json` file as JSON and any other file as TOML.
+    pub fn load(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let s = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read the config `{}`", path.display()))?;
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("json") => Self::from_json(&s),
+            _ => Self::from_toml(&s),
+        }
+    }
+
+    pub fn table(&self, name: &str) -> Option<&TableConfig> {
+        self.tables.get(name)
+    }
+}
+
+/// Settings applied to a table by [`crate::table::Table::apply_config`].
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TableConfig {
+    /// Query run by [`crate::table::Table::to_view`] when it is given an empty query.
+    pub default_query: String,
+    #[serde(flatten)]
+    pub options: ViewOptions,
+    pub views: BTreeMap<String, NamedView>,
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{format::Format, table_view::en::Alignment};
+
+    use super::*;
+
+    #[test]
+    fn test_from_toml() {
+        let config = Config::from_toml(
+            r#"
+[tables.conns]
+default_query = "sort cpu desc"
+column_order = ["name"]
+hidden_columns = ["pid"]
+
+[tables.conns.column_formats]
+mem = "bytes_iec"
+cpu = { percent = { precision = 1 } }
+
+[tables.conns.alignments]
+name = "right"
+
+[tables.conns.views.top-cpu]
+sql = "sort cpu desc\nlimit 10"
+float_precision = 2
+"#,
+        )
+        .unwrap();
+        let conns = config.table("conns").unwrap();
+        assert_eq!(conns.default_query, "sort cpu desc");
+        assert_eq!(conns.options.column_order, ["name"]);
+        assert!(conns.options.hidden_columns.contains("pid"));
+        assert_eq!(conns.options.column_formats["mem"], Format::BytesIec);
+        assert_eq!(
+            conns.options.column_formats["cpu"],
+            Format::Percent { precision: 1 }
+        );
+        assert_eq!(conns.options.alignments["name"], Alignment::Right);
+        let top_cpu = &conns.views["top-cpu"];
+        assert_eq!(top_cpu.sql, "sort cpu desc\nlimit 10");
+        assert_eq!(top_cpu.options.float_precision, Some(2));
+
+        let json = serde_json::to_string(&config).unwrap();
+        assert_eq!(Config::from_json(&json).unwrap(), config);
+    }
+}
@@ -0,0 +1,210 @@
+use std::{collections::HashMap, sync::Arc, time::SystemTime};
+
+use anyhow::{Context, bail};
+
+use crate::{
+    format,
+    row::LiteralValue,
+    table_view::{
+        TableView,
+        en::{Alignment, TableViewWrite},
+    },
+};
+
+/// Title of the column holding `+` for added, `-` for removed and `~` for changed rows.
+pub const CHANGE_COLUMN: &str = "_change";
+
+/// Values and displayed cells of a query result, taken by [`crate::table::Table::snapshot`].
+#[derive(Debug, Clone)]
+pub struct Snapshot {
+    headers: Vec<String>,
+    alignments: Vec<Alignment>,
+    rows: Vec<SnapshotRow>,
+    taken_at: SystemTime,
+}
+type SnapshotRow = (Vec<Option<LiteralValue>>, Vec<Arc<str>>);
+impl Snapshot {
+    pub(crate) fn new(
+        headers: Vec<String>,
+        alignments: Vec<Alignment>,
+        rows: Vec<SnapshotRow>,
+    ) -> Self {
+        Self {
+            headers,
+            alignments,
+            rows,
+            taken_at: SystemTime::now(),
+        }
+    }
+
+    pub fn headers(&self) -> &[String] {
+        &self.headers
+    }
+
+    pub fn len(&self) -> usize {
+        self.rows.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rows.is_empty()
+    }
+
+    pub fn taken_at(&self) -> SystemTime {
+        self.taken_at
+    }
+
+    fn position(&self, column: &str) -> Option<usize> {
+        self.headers.iter().position(|h| h == column)
+    }
+}
+
+/// Compare two snapshots of the same query, matching rows by the value of the `key` column.
+///
+/// Queries without a natural key can show [`crate::options::MetaColumn::Key`] and match rows by `_key`.
+///
+/// Unchanged rows are left out. Changed cells show `old→new` followed by the delta of numbers.
+/// Cells are compared as displayed, so changes hidden by the display precision do not count.
+pub fn diff(old: &Snapshot, new: &Snapshot, key: &str) -> anyhow::Result<TableViewWrite> {
+    let (Some(old_key), Some(new_key)) = (old.position(key), new.position(key)) else {
+        bail!("Both snapshots need the key column `{key}`");
+    };
+    let mut old_rows: HashMap<&str, usize> = HashMap::new();
+    for (i, (_, cells)) in old.rows.iter().enumerate() {
+        if old_rows.insert(&cells[old_key], i).is_some() {
+            bail!("Duplicate key `{}` in the old snapshot", cells[old_key]);
+        }
+    }
+    // Index of the column of the same title in the old snapshot
+    let old_columns: Vec<Option<usize>> = new.headers.iter().map(|h| old.position(h)).collect();
+
+    let mut rows: Vec<Arc<[Arc<str>]>> = vec![];
+    let mut matched = vec![false; old.rows.len()];
+    for (values, cells) in &new.rows {
+        let Some(&o) = old_rows.get(&*cells[new_key]) else {
+            rows.push(change_row("+", cells.iter().cloned()));
+            continue;
+        };
+        if matched[o] {
+            bail!("Duplicate key `{}` in the new snapshot", cells[new_key]);
+        }
+        matched[o] = true;
+        let (old_values, old_cells) = &old.rows[o];
+        let mut changed = false;
+        let row_cells = cells.iter().enumerate().map(|(i, cell)| {
+            let Some(j) = old_columns[i] else {
+                return cell.clone();
+            };
+            if old_cells[j] == *cell {
+                return cell.clone();
+            }
+            changed = true;
+            let mut s = format!("{}→{cell}", old_cells[j]);
+            if let (Some(old_value), Some(value)) = (&old_values[j], &values[i])
+                && let Some(delta) = delta(old_value, value, cell)
+            {
+                s.push_str(&format!(" ({delta})"));
+            }
+            s.into()
+        });
+        let row: Vec<Arc<str>> = row_cells.collect();
+        if changed {
+            rows.push(change_row("~", row));
+        }
+    }
+    for (o, (_, old_cells)) in old.rows.iter().enumerate() {
+        if matched[o] {
+            continue;
+        }
+        let cells = old_columns
+            .iter()
+            .map(|j| j.map(|j| old_cells[j].clone()).unwrap_or_else(|| "".into()));
+        rows.push(change_row("-", cells));
+    }
+
+    let titles = core::iter::once(CHANGE_COLUMN)
+        .chain(new.headers.iter().map(|h| h.as_str()))
+        .map(|t| t.into())
+        .collect();
+    let alignments = core::iter::once(Alignment::Left)
+        .chain(new.alignments.iter().copied())
+        .collect();
+    let t = TableView::new(titles, rows.into()).context("Failed to build the diff view")?;
+    Ok(TableViewWrite::new(t, alignments).unwrap())
+}
+
+fn change_row(change: &str, cells: impl IntoIterator<Item = Arc<str>>) -> Arc<[Arc<str>]> {
+    core::iter::once(change.into()).chain(cells).collect()
+}
+
+/// Return the signed difference of two numbers, with floats rounded to the decimal places of `displayed`.
+fn delta(old: &LiteralValue, new: &LiteralValue, displayed: &str) -> Option<String> {
+    let signed = |negative: bool, magnitude: String| {
+        let sign = if negative { '-' } else { '+' };
+        format!("{sign}{magnitude}")
+    };
+    let s = match (old, new) {
+        (LiteralValue::Int(a), LiteralValue::Int(b)) => format!("{:+}", *b as i128 - *a as i128),
+        (LiteralValue::UInt(a), LiteralValue::UInt(b)) => format!("{:+}", *b as i128 - *a as i128),
+        (LiteralValue::Float(a), LiteralValue::Float(b)) => {
+            let precision = displayed
+                .split_once('.')
+                .map(|(_, decimals)| decimals.chars().take_while(|c| c.is_ascii_digit()).count())
+                .unwrap_or(0);
+            let d = b - a;
+            signed(d < 0., format::fixed(d.abs(), precision))
+        }
+        (LiteralValue::ByteSize(a), LiteralValue::ByteSize(b)) => {
+            signed(b < a, format::bytes_iec(a.abs_diff(*b)))
+        }
+        (LiteralValue::Duration(a), LiteralValue::Duration(b)) => {
+            signed(b < a, format::duration(a.abs_diff(*b)))
+        }
+        _ => return None,
+    };
+    Some(s)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snapshot(rows: &[(&str, i64, f64)]) -> Snapshot {
+        let rows = rows
+            .iter()
+            .map(|&(name, x, y)| {
+                let values = vec![
+                    Some(name.to_string().into()),
+                    Some(x.into()),
+                    Some(y.into()),
+                ];
+                let cells = vec![
+                    name.into(),
+                    x.to_string().into(),
+                    format::fixed(y, 1).into(),
+                ];
+                (values, cells)
+            })
+            .collect();
+        Snapshot::new(
+            vec!["name".to_string(), "x".to_string(), "y".to_string()],
+            vec![Alignment::Left, Alignment::Right, Alignment::Right],
+            rows,
+        )
+    }
+
+    #[test]
+    fn test_diff() {
+        let old = snapshot(&[("a", 1, 0.5), ("b", 2, 1.0), ("c", 3, 1.5)]);
+        let new = snapshot(&[("a", 1, 0.5), ("b", 5, 0.25), ("d", 4, 2.0)]);
+        let view = diff(&old, &new, "name").unwrap();
+        assert_eq!(
+            view.to_string(),
+            "_change name x        y              
+~       b    2→5 (+3) 1.0→0.2 (-0.8) 
++       d           4            2.0 
+-       c           3            1.5 
+"
+        );
+        assert!(diff(&old, &new, "z").is_err());
+    }
+}
@@ -0,0 +1,545 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, RwLock},
+    time::Duration,
+};
+
+use anyhow::{bail, ensure};
+
+use crate::{
+    capacity::{Capacity, TableStats},
+    complete::{self, Completion},
+    diff::Snapshot,
+    options::{NamedView, ViewOptions},
+    row::{FieldVisitor, LiteralType, LiteralValue, ValueDisplay},
+    table::{RowAccess, RowGuard, RowKey, RowOwnedGuard, Table},
+    table_view::en::TableViewWrite,
+};
+
+/// Columns of a [`DynamicTable`].
+///
+/// Each change to the columns produces a new version of the schema; rows keep the version they were built under.
+#[derive(Debug, Clone)]
+pub struct DynamicSchema {
+    version: u64,
+    columns: Vec<(String, LiteralType)>,
+    /// Stable identity of each column across versions.
+    ids: Vec<u64>,
+    /// Value of each column in rows built before the column was added.
+    defaults: Vec<Option<LiteralValue>>,
+    next_id: u64,
+}
+impl DynamicSchema {
+    pub fn new(columns: Vec<(String, LiteralType)>) -> anyhow::Result<Self> {
+        for (i, (name, _)) in columns.iter().enumerate() {
+            check_name(&columns[..i], name)?;
+        }
+        let n = columns.len();
+        Ok(Self {
+            version: 0,
+            columns,
+            ids: (0..n as u64).collect(),
+            defaults: vec![None; n],
+            next_id: n as u64,
+        })
+    }
+
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+
+    pub fn columns(&self) -> &[(String, LiteralType)] {
+        &self.columns
+    }
+
+    pub fn position(&self, column: &str) -> Option<usize> {
+        self.columns.iter().position(|(name, _)| name == column)
+    }
+
+    fn position_by_id(&self, id: u64) -> Option<usize> {
+        self.ids.iter().position(|i| *i == id)
+    }
+
+    fn with_column(
+        &self,
+        name: String,
+        ty: LiteralType,
+        default: Option<LiteralValue>,
+    ) -> anyhow::Result<Self> {
+        check_name(&self.columns, &name)?;
+        let column = (name, ty);
+        check_type(&column, default.as_ref())?;
+        let mut next = self.clone();
+        next.version += 1;
+        next.columns.push(column);
+        next.ids.push(next.next_id);
+        next.defaults.push(default);
+        next.next_id += 1;
+        Ok(next)
+    }
+
+    fn without_column(&self, name: &str) -> anyhow::Result<Self> {
+        let Some(index) = self.position(name) else {
+            bail!("Unknown column `{name}`");
+        };
+        let mut next = self.clone();
+        next.version += 1;
+        next.columns.remove(index);
+        next.ids.remove(index);
+        next.defaults.remove(index);
+        Ok(next)
+    }
+}
+
+fn check_name(columns: &[(String, LiteralType)], name: &str) -> anyhow::Result<()> {
+    ensure!(
+        !name.is_empty() && !name.contains(char::is_whitespace),
+        "Invalid column name `{name}`"
+    );
+    ensure!(
+        !columns.iter().any(|(n, _)| n == name),
+        "Duplicate column name `{name}`"
+    );
+    Ok(())
+}
+
+fn check_type(
+    (name, ty): &(String, LiteralType),
+    value: Option<&LiteralValue>,
+) -> anyhow::Result<()> {
+    if let Some(value) = value {
+        ensure!(
+            value.literal_type() == *ty,
+            "Column `{name}` expects {ty:?} but got {:?}",
+            value.literal_type()
+        );
+    }
+    Ok(())
+}
+
+type SchemaCell = Arc<RwLock<Arc<DynamicSchema>>>;
+
+/// A row of a [`DynamicTable`] whose values always match the schema version it was built under.
+#[derive(Debug, Clone)]
+pub struct DynamicRow {
+    /// The latest schema of the table.
+    table_schema: SchemaCell,
+    schema: Arc<DynamicSchema>,
+    values: Vec<Option<LiteralValue>>,
+}
+impl DynamicRow {
+    /// Return the values in the order of [`DynamicRow::schema`].
+    pub fn values(&self) -> &[Option<LiteralValue>] {
+        &self.values
+    }
+
+    /// Return the schema version the row was built under or last upgraded to.
+    pub fn schema(&self) -> &DynamicSchema {
+        &self.schema
+    }
+
+    /// Return the value as views show it: under the latest schema of the table, with the default of a column added after the row was built.
+    pub fn get(&self, column: &str) -> Option<LiteralValue> {
+        let latest = self.table_schema.read().unwrap().clone();
+        let index = latest.position(column)?;
+        value_under(&latest, index, &self.schema, &self.values)
+    }
+
+    /// Set a value, upgrading the row to the latest schema if the column was added after the row was built.
+    pub fn set(&mut self, column: &str, value: Option<LiteralValue>) -> anyhow::Result<()> {
+        if self.schema.position(column).is_none() {
+            self.upgrade();
+        }
+        let Some(index) = self.schema.position(column) else {
+            bail!("Unknown column `{column}`");
+        };
+        check_type(&self.schema.columns[index], value.as_ref())?;
+        self.values[index] = value;
+        Ok(())
+    }
+
+    /// Move the row to the latest schema of the table.
+    pub fn upgrade(&mut self) {
+        let latest = self.table_schema.read().unwrap().clone();
+        if Arc::ptr_eq(&latest, &self.schema) {
+            return;
+        }
+        self.values = (0..latest.columns.len())
+            .map(|i| value_under(&latest, i, &self.schema, &self.values))
+            .collect();
+        self.schema = latest;
+    }
+}
+impl ValueDisplay for DynamicRow {}
+
+/// Return the value of column `index` of `schema` from values built under `row_schema`.
+fn value_under(
+    schema: &DynamicSchema,
+    index: usize,
+    row_schema: &DynamicSchema,
+    values: &[Option<LiteralValue>],
+) -> Option<LiteralValue> {
+    match row_schema.position_by_id(schema.ids[index]) {
+        Some(i) => values[i].clone(),
+        None => schema.defaults[index].clone(),
+    }
+}
+
+/// A table whose columns are decided at runtime instead of by [`crate::row::TableRow::schema`].
+///
+/// Columns can be added and removed while rows are alive.
+#[derive(Debug, Clone)]
+pub struct DynamicTable {
+    schema: SchemaCell,
+    table: Table<DynamicRow>,
+}
+impl DynamicTable {
+    pub fn new(schema: DynamicSchema) -> Self {
+        Self {
+            schema: Arc::new(RwLock::new(Arc::new(schema))),
+            table: Table::new(),
+        }
+    }
+
+    pub fn schema(&self) -> Arc<DynamicSchema> {
+        self.schema.read().unwrap().clone()
+    }
+
+    /// Add a column that reads as `default` in the existing rows.
+    pub fn add_column(
+        &self,
+        name: String,
+        ty: LiteralType,
+        default: Option<LiteralValue>,
+    ) -> anyhow::Result<()> {
+        let mut schema = self.schema.write().unwrap();
+        *schema = Arc::new(schema.with_column(name, ty, default)?);
+        self.table.bump_generation();
+        Ok(())
+    }
+
+    pub fn remove_column(&self, name: &str) -> anyhow::Result<()> {
+        let mut schema = self.schema.write().unwrap();
+        *schema = Arc::new(schema.without_column(name)?);
+        self.table.bump_generation();
+        Ok(())
+    }
+
+    /// Build a row from values in the order of the schema.
+    pub fn row(&self, values: Vec<Option<LiteralValue>>) -> anyhow::Result<DynamicRow> {
+        let schema = self.schema();
+        ensure!(
+            values.len() == schema.columns.len(),
+            "Expected {} values but got {}",
+            schema.columns.len(),
+            values.len()
+        );
+        for (column, value) in schema.columns.iter().zip(&values) {
+            check_type(column, value.as_ref())?;
+        }
+        Ok(DynamicRow {
+            table_schema: self.schema.clone(),
+            schema,
+            values,
+        })
+    }
+
+    /// Build a row from values keyed by column names; missing columns are null.
+    pub fn row_from_map(
+        &self,
+        mut values: HashMap<String, LiteralValue>,
+    ) -> anyhow::Result<DynamicRow> {
+        let row = self
+            .schema()
+            .columns
+            .iter()
+            .map(|(name, _)| values.remove(name))
+            .collect();
+        if let Some(name) = values.keys().next() {
+            bail!("Unknown column `{name}`");
+        }
+        self.row(row)
+    }
+
+    pub fn insert(&self, row: DynamicRow) -> anyhow::Result<RowKey> {
+        self.check_row(&row)?;
+        Ok(self.table.insert(row))
+    }
+
+    /// See [`Table::try_insert`].
+    pub fn try_insert(&self, row: DynamicRow) -> anyhow::Result<RowKey> {
+        self.check_row(&row)?;
+        self.table.try_insert(row)
+    }
+
+    pub fn set_scope(&self, row: DynamicRow) -> anyhow::Result<RowGuard<'_, DynamicRow>> {
+        self.check_row(&row)?;
+        Ok(self.table.set_scope(row))
+    }
+
+    pub fn set_scope_owned(&self, row: DynamicRow) -> anyhow::Result<RowOwnedGuard<DynamicRow>> {
+        self.check_row(&row)?;
+        Ok(self.table.set_scope_owned(row))
+    }
+
+    pub fn remove(&self, key: RowKey) -> Option<DynamicRow> {
+        self.table.remove(key)
+    }
+
+    /// See [`Table::inspect`].
+    pub fn inspect<T>(&self, key: RowKey, f: impl FnOnce(&DynamicRow) -> T) -> Option<T> {
+        self.table.inspect(key, f)
+    }
+
+    pub fn generation(&self) -> u64 {
+        self.table.generation()
+    }
+
+    /// See [`Table::set_query_cache`].
+    pub fn set_query_cache(&self, max_staleness: Option<Duration>) {
+        self.table.set_query_cache(max_staleness);
+    }
+
+    /// See [`Table::set_capacity`].
+    pub fn set_capacity(&self, capacity: Option<Capacity>) -> anyhow::Result<()> {
+        self.table.set_capacity(capacity)
+    }
+
+    pub fn stats(&self) -> TableStats {
+        self.table.stats()
+    }
+
+    /// See [`Table::stats_view`].
+    pub fn stats_view(&self, sql: &str) -> anyhow::Result<TableViewWrite> {
+        self.table.stats_view(sql)
+    }
+
+    fn check_row(&self, row: &DynamicRow) -> anyhow::Result<()> {
+        ensure!(
+            Arc::ptr_eq(&row.table_schema, &self.schema),
+            "The row was built for another table"
+        );
+        Ok(())
+    }
+
+    /// See [`Table::register_view`].
+    pub fn register_view(&self, name: String, view: NamedView) -> Option<NamedView> {
+        self.table.register_view(name, view)
+    }
+
+    pub fn remove_view(&self, name: &str) -> Option<NamedView> {
+        self.table.remove_view(name)
+    }
+
+    pub fn views(&self) -> Vec<(String, NamedView)> {
+        self.table.views()
+    }
+
+    pub fn set_default_view(&self, view: NamedView) {
+        self.table.set_default_view(view);
+    }
+
+    #[cfg(feature = "config")]
+    pub fn apply_config(&self, config: &crate::config::TableConfig) {
+        self.table.apply_config(config);
+    }
+
+    pub fn to_named_view(&self, name: &str) -> anyhow::Result<TableViewWrite> {
+        let view = self.table.named_view(name)?;
+        self.to_view_with(&view.sql, &view.options)
+    }
+
+    /// See [`Table::snapshot`].
+    pub fn snapshot(&self, sql: &str, options: &ViewOptions) -> anyhow::Result<Snapshot> {
+        let schema = self.schema();
+        let access = DynamicAccess {
+            schema: schema.clone(),
+        };
+        self.table
+            .query_snapshot(&schema.columns, &access, sql, options)
+    }
+
+    /// Suggest completions against the current schema; see [`Table::complete`].
+    pub fn complete(&self, sql: &str, cursor: usize) -> Vec<Completion> {
+        complete::complete(sql, cursor, &self.schema().columns)
+    }
+
+    /// See [`Table::to_view`].
+    pub fn to_view(&self, sql: &str) -> anyhow::Result<TableViewWrite> {
+        let default = self.table.default_view();
+        let sql = if sql.trim().is_empty() {
+            &default.sql
+        } else {
+            sql
+        };
+        self.to_view_with(sql, &default.options)
+    }
+
+    pub fn to_view_with(&self, sql: &str, options: &ViewOptions) -> anyhow::Result<TableViewWrite> {
+        // Columns change the generation after the schema, so a generation read first covers the schema read after it.
+        // Holding the schema lock instead would invert the lock order of `inspect_mut` upgrading a row.
+        let generation = self.table.generation();
+        let schema = self.schema();
+        let access = DynamicAccess {
+            schema: schema.clone(),
+        };
+        self.table
+            .query(&schema.columns, &access, Some(generation), sql, options)
+    }
+}
+
+/// Read rows of any schema version under the latest one.
+struct DynamicAccess {
+    schema: Arc<DynamicSchema>,
+}
+impl RowAccess<DynamicRow> for DynamicAccess {
+    fn visit_fields(&self, row: &DynamicRow, visitor: &mut dyn FieldVisitor) -> anyhow::Result<()> {
+        let current = Arc::ptr_eq(&row.schema, &self.schema);
+        for i in 0..self.schema.columns.len() {
+            let value = match current {
+                true => row.values[i].clone(),
+                false => value_under(&self.schema, i, &row.schema, &row.values),
+            };
+            visitor.visit(i, value);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dynamic_table() {
+        let schema = DynamicSchema::new(vec![
+            ("name".to_string(), LiteralType::String),
+            ("x".to_string(), LiteralType::Int),
+        ])
+        .unwrap();
+        let table = DynamicTable::new(schema);
+
+        let a = table
+            .row(vec![Some("a".to_string().into()), Some(1_i64.into())])
+            .unwrap();
+        let _a = table.set_scope(a).unwrap();
+        let b = table
+            .row_from_map([("name".to_string(), "b".to_string().into())].into())
+            .unwrap();
+        let b = table.set_scope(b).unwrap();
+        b.inspect_mut(|r| r.set("x", Some(0_i64.into())))
+            .unwrap()
+            .unwrap();
+
+        assert!(table.row(vec![None]).is_err());
+        assert!(table.row(vec![None, Some(1_u64.into())]).is_err());
+        assert!(
+            table
+                .row_from_map([("y".to_string(), 1_i64.into())].into())
+                .is_err()
+        );
+
+        let view = table.to_view("sort x").unwrap();
+        assert_eq!(
+            view.to_string(),
+            "name x 
+b    0 
+a    1 
+"
+        );
+    }
+
+    #[test]
+    fn test_schema_evolution() {
+        let schema = DynamicSchema::new(vec![
+            ("name".to_string(), LiteralType::String),
+            ("x".to_string(), LiteralType::Int),
+        ])
+        .unwrap();
+        let table = DynamicTable::new(schema);
+        let a = table
+            .row(vec![Some("a".to_string().into()), Some(1_i64.into())])
+            .unwrap();
+        let a = table.set_scope(a).unwrap();
+
+        table
+            .add_column("y".to_string(), LiteralType::UInt, Some(7_u64.into()))
+            .unwrap();
+        table.remove_column("x").unwrap();
+        assert_eq!(table.schema().version(), 2);
+        assert!(
+            table
+                .add_column("name".to_string(), LiteralType::String, None)
+                .is_err()
+        );
+
+        let b = table
+            .row(vec![Some("b".to_string().into()), Some(2_u64.into())])
+            .unwrap();
+        let _b = table.set_scope(b).unwrap();
+        let view = table.to_view("sort y").unwrap();
+        assert_eq!(
+            view.to_string(),
+            "name y 
+b    2 
+a    7 
+"
+        );
+
+        // The old row reads the default of the new column and not the removed one
+        let (y, x) = a.inspect_mut(|r| (r.get("y"), r.get("x"))).unwrap();
+        assert!(matches!(y, Some(LiteralValue::UInt(7))));
+        assert!(x.is_none());
+
+        // The old row upgrades itself to reach the new column
+        a.inspect_mut(|r| r.set("y", Some(1_u64.into())))
+            .unwrap()
+            .unwrap();
+        let view = table.to_view("sort y").unwrap();
+        assert_eq!(
+            view.to_string(),
+            "name y 
+a    1 
+b    2 
+"
+        );
+    }
+
+    #[test]
+    fn test_add_column_while_querying() {
+        let schema = DynamicSchema::new(vec![("x".to_string(), LiteralType::Int)]).unwrap();
+        let table = DynamicTable::new(schema);
+        table.set_query_cache(Some(Duration::ZERO));
+        let a = table.row(vec![Some(1_i64.into())]).unwrap();
+        let a = table.set_scope(a).unwrap();
+
+        // Upgrading a row under the rows lock, adding columns and querying must not deadlock
+        const ROUNDS: usize = 200;
+        std::thread::scope(|s| {
+            s.spawn(|| {
+                for _ in 0..ROUNDS {
+                    a.inspect_mut(|r| {
+                        // Widen the window in which the rows are locked before the schema
+                        std::thread::sleep(Duration::from_micros(100));
+                        r.upgrade()
+                    });
+                }
+            });
+            s.spawn(|| {
+                for i in 0..ROUNDS {
+                    table
+                        .add_column(format!("c{i}"), LiteralType::Int, None)
+                        .unwrap();
+                }
+            });
+            s.spawn(|| {
+                for _ in 0..ROUNDS {
+                    table.to_view("select x").unwrap();
+                }
+            });
+        });
+        let view = table.to_view("").unwrap();
+        assert!(view.to_string().starts_with("x c0 "));
+        assert!(view.to_string().contains(&format!("c{} ", ROUNDS - 1)));
+    }
+}
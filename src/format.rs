@@ -0,0 +1,283 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::row::LiteralValue;
+
+/// A reusable way to render the values of a column.
+///
+/// Attach it to a column with [`crate::row::ValueDisplay::column_format`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum Format {
+    /// Byte count in SI units, e.g. `3.6 MB`.
+    BytesSi,
+    /// Byte count in IEC units, e.g. `3.4 MiB`.
+    BytesIec,
+    /// Number already in percent, e.g. `12.3%`.
+    Percent { precision: usize },
+    /// Ratio scaled to percent, e.g. `0.123` as `12.3%`.
+    Ratio { precision: usize },
+    /// Seconds or a duration, e.g. `1.2s`.
+    Duration,
+    /// Integer with thousands separators, e.g. `1,234,567`.
+    Thousands,
+    /// Number with a fixed count of decimal places.
+    Fixed { precision: usize },
+    /// Bytes in lowercase hexadecimal.
+    Hex,
+    /// Bytes in standard padded base64.
+    Base64,
+}
+impl Format {
+    /// Return `None` if the format does not apply to the value.
+    pub fn format(&self, value: &LiteralValue) -> Option<String> {
+        let s = match self {
+            Format::BytesSi => bytes_si(as_u64(value)?),
+            Format::BytesIec => bytes_iec(as_u64(value)?),
+            Format::Percent { precision } => percent(as_f64(value)?, *precision),
+            Format::Ratio { precision } => percent(as_f64(value)? * 100., *precision),
+            Format::Duration => match value {
+                LiteralValue::Duration(v) => duration(*v),
+                _ => duration(Duration::try_from_secs_f64(as_f64(value)?).ok()?),
+            },
+            Format::Thousands => match value {
+                LiteralValue::UInt(v) | LiteralValue::ByteSize(v) => thousands((*v).into()),
+                LiteralValue::Int(v) => thousands((*v).into()),
+                _ => return None,
+            },
+            Format::Fixed { precision } => fixed(as_f64(value)?, *precision),
+            Format::Hex => match value {
+                LiteralValue::Bytes(v) => hex(v),
+                _ => return None,
+            },
+            Format::Base64 => match value {
+                LiteralValue::Bytes(v) => base64(v),
+                _ => return None,
+            },
+        };
+        Some(s)
+    }
+}
+
+fn as_u64(value: &LiteralValue) -> Option<u64> {
+    match value {
+        LiteralValue::UInt(v) | LiteralValue::ByteSize(v) => Some(*v),
+        LiteralValue::Int(v) => (*v).try_into().ok(),
+        _ => None,
+    }
+}
+
+fn as_f64(value: &LiteralValue) -> Option<f64> {
+    match value {
+        LiteralValue::UInt(v) | LiteralValue::ByteSize(v) => Some(*v as f64),
+        LiteralValue::Int(v) => Some(*v as f64),
+        LiteralValue::Float(v) => Some(*v),
+        LiteralValue::Duration(v) => Some(v.as_secs_f64()),
+        _ => None,
+    }
+}
+
+/// Format a timestamp in ISO 8601 with millisecond precision in UTC.
+pub fn timestamp(value: SystemTime) -> String {
+    let millis: i128 = match value.duration_since(UNIX_EPOCH) {
+        Ok(d) => d.as_millis() as i128,
+        Err(e) => -(e.duration().as_millis() as i128),
+    };
+    const MILLIS_PER_DAY: i128 = 24 * 60 * 60 * 1000;
+    let days = millis.div_euclid(MILLIS_PER_DAY) as i64;
+    let millis_of_day = millis.rem_euclid(MILLIS_PER_DAY) as u64;
+    let (year, month, day) = civil_from_days(days);
+    let hour = millis_of_day / (60 * 60 * 1000);
+    let minute = millis_of_day / (60 * 1000) % 60;
+    let second = millis_of_day / 1000 % 60;
+    let milli = millis_of_day % 1000;
+    format!("{year:04}-{month:02}-{day:02}T{hour:02}:{minute:02}:{second:02}.{milli:03}Z")
+}
+
+/// Convert days since the Unix epoch to a proleptic Gregorian date.
+///
+/// Ref: <https://howardhinnant.github.io/date_algorithms.html#civil_from_days>
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+/// Format a duration with the largest unit that keeps it above one, e.g. `1.2s` or `3m5s`.
+pub fn duration(value: Duration) -> String {
+    let secs = value.as_secs();
+    if secs >= 24 * 60 * 60 {
+        return format!("{}d{}h", secs / (24 * 60 * 60), secs / (60 * 60) % 24);
+    }
+    if secs >= 60 * 60 {
+        return format!("{}h{}m", secs / (60 * 60), secs / 60 % 60);
+    }
+    if secs >= 60 {
+        return format!("{}m{}s", secs / 60, secs % 60);
+    }
+    let nanos = value.as_nanos();
+    if nanos >= 1_000_000_000 {
+        return format!("{:.1}s", value.as_secs_f64());
+    }
+    if nanos >= 1_000_000 {
+        return format!("{:.1}ms", nanos as f64 / 1_000_000.);
+    }
+    if nanos >= 1_000 {
+        return format!("{:.1}µs", nanos as f64 / 1_000.);
+    }
+    format!("{nanos}ns")
+}
+
+/// Format a number of bytes in IEC units, e.g. `3.4 MiB`.
+pub fn bytes_iec(value: u64) -> String {
+    bytes(value, 1024., ["KiB", "MiB", "GiB", "TiB", "PiB", "EiB"])
+}
+
+/// Format a number of bytes in SI units, e.g. `3.6 MB`.
+pub fn bytes_si(value: u64) -> String {
+    bytes(value, 1000., ["kB", "MB", "GB", "TB", "PB", "EB"])
+}
+
+fn bytes(value: u64, base: f64, units: [&str; 6]) -> String {
+    if (value as f64) < base {
+        return format!("{value} B");
+    }
+    let mut scaled = value as f64 / base;
+    let mut unit = units[0];
+    for next in &units[1..] {
+        if scaled < base {
+            break;
+        }
+        scaled /= base;
+        unit = next;
+    }
+    format!("{scaled:.1} {unit}")
+}
+
+/// Format a number already in percent, e.g. `12.3%`.
+pub fn percent(value: f64, precision: usize) -> String {
+    format!("{value:.precision$}%")
+}
+
+/// Format an integer with comma thousands separators, e.g. `-1,234,567`.
+pub fn thousands(value: i128) -> String {
+    let digits = value.unsigned_abs().to_string();
+    let mut s = String::with_capacity(digits.len() + digits.len() / 3 + 1);
+    if value < 0 {
+        s.push('-');
+    }
+    for (i, c) in digits.chars().enumerate() {
+        if i != 0 && (digits.len() - i).is_multiple_of(3) {
+            s.push(',');
+        }
+        s.push(c);
+    }
+    s
+}
+
+/// Format a number with a fixed count of decimal places.
+pub fn fixed(value: f64, precision: usize) -> String {
+    format!("{value:.precision$}")
+}
+
+/// Format bytes in lowercase hexadecimal, e.g. `00ff`.
+pub fn hex(value: &[u8]) -> String {
+    use core::fmt::Write;
+    let mut s = String::with_capacity(value.len() * 2);
+    for b in value {
+        write!(s, "{b:02x}").unwrap();
+    }
+    s
+}
+
+/// Format bytes in standard padded base64, e.g. `AP8=`.
+pub fn base64(value: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut s = String::with_capacity(value.len().div_ceil(3) * 4);
+    for chunk in value.chunks(3) {
+        let b = [
+            chunk[0],
+            chunk.get(1).copied().unwrap_or(0),
+            chunk.get(2).copied().unwrap_or(0),
+        ];
+        let n = u32::from(b[0]) << 16 | u32::from(b[1]) << 8 | u32::from(b[2]);
+        for i in 0..4 {
+            if i <= chunk.len() {
+                s.push(ALPHABET[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                s.push('=');
+            }
+        }
+    }
+    s
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_timestamp() {
+        assert_eq!(timestamp(UNIX_EPOCH), "1970-01-01T00:00:00.000Z");
+        let t = UNIX_EPOCH + Duration::from_millis(1_709_210_096_789);
+        assert_eq!(timestamp(t), "2024-02-29T12:34:56.789Z");
+        let t = UNIX_EPOCH - Duration::from_millis(1);
+        assert_eq!(timestamp(t), "1969-12-31T23:59:59.999Z");
+    }
+
+    #[test]
+    fn test_duration() {
+        assert_eq!(duration(Duration::ZERO), "0ns");
+        assert_eq!(duration(Duration::from_micros(1500)), "1.5ms");
+        assert_eq!(duration(Duration::from_millis(1200)), "1.2s");
+        assert_eq!(duration(Duration::from_secs(185)), "3m5s");
+        assert_eq!(duration(Duration::from_secs(3 * 3600 + 120)), "3h2m");
+        assert_eq!(duration(Duration::from_secs(2 * 86400 + 3600)), "2d1h");
+    }
+
+    #[test]
+    fn test_bytes_iec() {
+        assert_eq!(bytes_iec(512), "512 B");
+        assert_eq!(bytes_iec(1024), "1.0 KiB");
+        assert_eq!(bytes_iec(3_565_158), "3.4 MiB");
+        assert_eq!(bytes_si(3_565_158), "3.6 MB");
+    }
+
+    #[test]
+    fn test_format() {
+        let f = |format: Format, value: LiteralValue| format.format(&value);
+        assert_eq!(
+            f(Format::Thousands, 1234567_u64.into()).unwrap(),
+            "1,234,567"
+        );
+        assert_eq!(f(Format::Thousands, (-1234_i64).into()).unwrap(), "-1,234");
+        assert_eq!(f(Format::Thousands, 123_i64.into()).unwrap(), "123");
+        assert_eq!(
+            f(Format::Percent { precision: 1 }, 12.345.into()).unwrap(),
+            "12.3%"
+        );
+        assert_eq!(
+            f(Format::Ratio { precision: 0 }, 0.5.into()).unwrap(),
+            "50%"
+        );
+        assert_eq!(f(Format::Duration, 1.25.into()).unwrap(), "1.2s");
+        assert_eq!(
+            f(Format::Fixed { precision: 2 }, 1.0.into()).unwrap(),
+            "1.00"
+        );
+        assert!(f(Format::BytesIec, "x".to_string().into()).is_none());
+        assert_eq!(f(Format::Hex, vec![0, 255].into()).unwrap(), "00ff");
+        assert_eq!(
+            f(Format::Base64, b"foob".to_vec().into()).unwrap(),
+            "Zm9vYg=="
+        );
+        assert_eq!(f(Format::Base64, b"foo".to_vec().into()).unwrap(), "Zm9v");
+    }
+}
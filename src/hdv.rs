@@ -1,51 +1,300 @@
+use core::{
+    fmt,
+    marker::PhantomData,
+    ops::{Deref, DerefMut},
+};
+
 use hdv::serde::{HdvScheme, HdvSerialize};
 
-use crate::row::TableRow;
+use crate::{
+    format::{self, Format},
+    row::{LiteralType, LiteralValue, TableRow, ValueDisplay},
+};
+
+/// Separator hdv puts between the names of nested objects and their fields.
+const HDV_SEPARATOR: char = '.';
+
+/// Control how an hdv object wrapped in [`Hdv`] is exposed as a [`TableRow`].
+///
+/// Objects used as rows without the wrapper get the defaults.
+pub trait HdvRow {
+    /// Prepended to every column name.
+    const PREFIX: &'static str = "";
+    /// Put between the names of nested objects and their fields, e.g. `_` for `addr_port`.
+    const SEPARATOR: &'static str = ".";
+    const F32: F32Mapping = F32Mapping::Cast;
+    const BYTES: BytesMapping = BytesMapping::Bytes;
+}
+
+/// How `F32` atoms become [`LiteralValue::Float`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum F32Mapping {
+    /// Widen the value, e.g. `0.1` becomes `0.10000000149011612`.
+    Cast,
+    /// Keep the shortest decimal representation of the `f32`, e.g. `0.1` stays `0.1`.
+    Shortest,
+}
+
+/// How `Bytes` atoms are exposed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BytesMapping {
+    /// [`LiteralType::Bytes`].
+    Bytes,
+    /// [`LiteralType::String`] in lowercase hexadecimal.
+    Hex,
+    /// [`LiteralType::String`] in standard padded base64.
+    Base64,
+}
+
+/// The [`HdvRow`] defaults.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DefaultHdvRow;
+impl HdvRow for DefaultHdvRow {}
+
+/// Expose an hdv object as a table row with the options of `O`.
+///
+/// Atoms of an optional nested object that is `None` are null.
+pub struct Hdv<T, O = DefaultHdvRow> {
+    pub object: T,
+    options: PhantomData<fn() -> O>,
+}
+impl<T, O> Hdv<T, O> {
+    pub fn new(object: T) -> Self {
+        Self {
+            object,
+            options: PhantomData,
+        }
+    }
+}
+impl<T: fmt::Debug, O> fmt::Debug for Hdv<T, O> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Hdv").field(&self.object).finish()
+    }
+}
+impl<T: Clone, O> Clone for Hdv<T, O> {
+    fn clone(&self) -> Self {
+        Self::new(self.object.clone())
+    }
+}
+impl<T, O> TableRow for Hdv<T, O>
+where
+    T: HdvScheme + HdvSerialize,
+    O: HdvRow,
+{
+    fn schema() -> Vec<(String, LiteralType)> {
+        schema::<T, O>()
+    }
+
+    fn fields(&self) -> Vec<Option<LiteralValue>> {
+        fields::<T, O>(&self.object)
+    }
+}
+impl<T: ValueDisplay, O> ValueDisplay for Hdv<T, O> {
+    fn display_value(header: &str, value: Option<LiteralValue>) -> String {
+        T::display_value(header, value)
+    }
+    fn column_format(header: &str) -> Option<Format> {
+        T::column_format(header)
+    }
+    fn custom_display(header: &str) -> bool {
+        T::custom_display(header)
+    }
+}
+impl<T, O> Deref for Hdv<T, O> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        &self.object
+    }
+}
+impl<T, O> DerefMut for Hdv<T, O> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.object
+    }
+}
 
 impl<T> TableRow for T
 where
     T: HdvScheme + HdvSerialize,
 {
-    fn schema() -> Vec<(String, crate::row::LiteralType)> {
-        let object_scheme = <Self as HdvScheme>::object_scheme();
-        let atom_schemes = object_scheme.atom_schemes();
-        atom_schemes
-            .into_iter()
-            .map(|atom| {
-                (
-                    atom.name,
-                    match atom.r#type {
-                        hdv::format::AtomType::String => crate::row::LiteralType::String,
-                        hdv::format::AtomType::Bytes => crate::row::LiteralType::String,
-                        hdv::format::AtomType::U64 => crate::row::LiteralType::UInt,
-                        hdv::format::AtomType::I64 => crate::row::LiteralType::Int,
-                        hdv::format::AtomType::F32 => crate::row::LiteralType::Float,
-                        hdv::format::AtomType::F64 => crate::row::LiteralType::Float,
-                        hdv::format::AtomType::Bool => crate::row::LiteralType::Bool,
+    fn schema() -> Vec<(String, LiteralType)> {
+        schema::<T, DefaultHdvRow>()
+    }
+
+    fn fields(&self) -> Vec<Option<LiteralValue>> {
+        fields::<T, DefaultHdvRow>(self)
+    }
+}
+
+/// A row stored in hdv files as [`HdvObject::Object`], by [`crate::table::Table::dump_hdv`] and [`crate::table::Table::load_hdv`].
+pub trait HdvObject {
+    type Object: HdvScheme;
+    fn object(&self) -> &Self::Object;
+    fn from_object(object: Self::Object) -> Self;
+}
+impl<T: HdvScheme> HdvObject for T {
+    type Object = T;
+    fn object(&self) -> &Self::Object {
+        self
+    }
+    fn from_object(object: Self::Object) -> Self {
+        object
+    }
+}
+impl<T: HdvScheme, O> HdvObject for Hdv<T, O> {
+    type Object = T;
+    fn object(&self) -> &Self::Object {
+        &self.object
+    }
+    fn from_object(object: Self::Object) -> Self {
+        Self::new(object)
+    }
+}
+
+fn schema<T: HdvScheme, O: HdvRow>() -> Vec<(String, LiteralType)> {
+    let object_scheme = T::object_scheme();
+    let atom_schemes = object_scheme.atom_schemes();
+    atom_schemes
+        .into_iter()
+        .map(|atom| {
+            (
+                column_name(O::PREFIX, O::SEPARATOR, &atom.name),
+                match atom.r#type {
+                    hdv::format::AtomType::String => LiteralType::String,
+                    hdv::format::AtomType::Bytes => match O::BYTES {
+                        BytesMapping::Bytes => LiteralType::Bytes,
+                        BytesMapping::Hex | BytesMapping::Base64 => LiteralType::String,
                     },
-                )
-            })
-            .collect()
-    }
-
-    fn fields(&self) -> Vec<Option<crate::row::LiteralValue>> {
-        let mut atoms = vec![];
-        <Self as HdvSerialize>::serialize(self, &mut atoms);
-        atoms
-            .into_iter()
-            .map(|x| {
-                x.map(|x| match x {
-                    hdv::format::AtomValue::String(x) => crate::row::LiteralValue::String(x),
-                    hdv::format::AtomValue::Bytes(x) => {
-                        crate::row::LiteralValue::String(format!("{x:x?}").into())
-                    }
-                    hdv::format::AtomValue::U64(x) => crate::row::LiteralValue::UInt(x),
-                    hdv::format::AtomValue::I64(x) => crate::row::LiteralValue::Int(x),
-                    hdv::format::AtomValue::F32(x) => crate::row::LiteralValue::Float(x as f64),
-                    hdv::format::AtomValue::F64(x) => crate::row::LiteralValue::Float(x),
-                    hdv::format::AtomValue::Bool(x) => crate::row::LiteralValue::Bool(x),
-                })
+                    hdv::format::AtomType::U64 => LiteralType::UInt,
+                    hdv::format::AtomType::I64 => LiteralType::Int,
+                    hdv::format::AtomType::F32 => LiteralType::Float,
+                    hdv::format::AtomType::F64 => LiteralType::Float,
+                    hdv::format::AtomType::Bool => LiteralType::Bool,
+                },
+            )
+        })
+        .collect()
+}
+
+fn fields<T: HdvSerialize, O: HdvRow>(object: &T) -> Vec<Option<LiteralValue>> {
+    let mut atoms = vec![];
+    object.serialize(&mut atoms);
+    atoms
+        .into_iter()
+        .map(|x| {
+            x.map(|x| match x {
+                hdv::format::AtomValue::String(x) => LiteralValue::String(x),
+                hdv::format::AtomValue::Bytes(x) => match O::BYTES {
+                    BytesMapping::Bytes => LiteralValue::Bytes(x[..].into()),
+                    BytesMapping::Hex => LiteralValue::String(format::hex(&x).into()),
+                    BytesMapping::Base64 => LiteralValue::String(format::base64(&x).into()),
+                },
+                hdv::format::AtomValue::U64(x) => LiteralValue::UInt(x),
+                hdv::format::AtomValue::I64(x) => LiteralValue::Int(x),
+                hdv::format::AtomValue::F32(x) => match O::F32 {
+                    F32Mapping::Cast => LiteralValue::Float(x as f64),
+                    F32Mapping::Shortest => LiteralValue::Float(x.to_string().parse().unwrap()),
+                },
+                hdv::format::AtomValue::F64(x) => LiteralValue::Float(x),
+                hdv::format::AtomValue::Bool(x) => LiteralValue::Bool(x),
             })
-            .collect()
+        })
+        .collect()
+}
+
+fn column_name(prefix: &str, separator: &str, atom_name: &str) -> String {
+    let mut name = prefix.to_string();
+    for (i, part) in atom_name.split(HDV_SEPARATOR).enumerate() {
+        if i != 0 {
+            name.push_str(separator);
+        }
+        name.push_str(part);
+    }
+    name
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_column_name() {
+        assert_eq!(column_name("", ".", "addr.port"), "addr.port");
+        assert_eq!(column_name("conn_", "_", "addr.port"), "conn_addr_port");
+        assert_eq!(column_name("", "_", "id"), "id");
+    }
+
+    #[test]
+    fn test_optional_object() {
+        use hdv::format::{AtomScheme, AtomType, AtomValue, ObjectScheme};
+
+        use crate::table::Table;
+
+        struct Conn {
+            id: u64,
+            peer: Option<Peer>,
+        }
+        struct Peer {
+            ip: String,
+            port: u64,
+        }
+        impl HdvScheme for Conn {
+            fn object_scheme() -> ObjectScheme {
+                let atom = |name: &str, r#type| AtomScheme {
+                    name: name.to_string(),
+                    r#type,
+                };
+                ObjectScheme {
+                    fields: vec![
+                        atom("id", AtomType::U64),
+                        atom("peer.ip", AtomType::String),
+                        atom("peer.port", AtomType::U64),
+                    ],
+                }
+            }
+        }
+        impl HdvSerialize for Conn {
+            fn serialize(&self, values: &mut Vec<Option<AtomValue>>) {
+                values.push(Some(AtomValue::U64(self.id)));
+                values.push(
+                    self.peer
+                        .as_ref()
+                        .map(|p| AtomValue::String(p.ip.as_str().into())),
+                );
+                values.push(self.peer.as_ref().map(|p| AtomValue::U64(p.port)));
+            }
+        }
+        impl ValueDisplay for Conn {}
+        struct Flat;
+        impl HdvRow for Flat {
+            const PREFIX: &'static str = "conn_";
+            const SEPARATOR: &'static str = "_";
+        }
+
+        let names = |schema: Vec<(String, LiteralType)>| -> Vec<String> {
+            schema.into_iter().map(|(name, _)| name).collect()
+        };
+        assert_eq!(names(Conn::schema()), ["id", "peer.ip", "peer.port"]);
+        assert_eq!(
+            names(Hdv::<Conn, Flat>::schema()),
+            ["conn_id", "conn_peer_ip", "conn_peer_port"]
+        );
+
+        let table = Table::new();
+        let _a = table.set_scope(Hdv::<_, Flat>::new(Conn {
+            id: 1,
+            peer: Some(Peer {
+                ip: "::1".to_string(),
+                port: 80,
+            }),
+        }));
+        let _b = table.set_scope(Hdv::new(Conn { id: 2, peer: None }));
+        assert_eq!(
+            table.to_view("sort conn_id").unwrap().to_string(),
+            "conn_id conn_peer_ip conn_peer_port 
+      1 ::1                      80 
+      2                             
+"
+        );
     }
 }
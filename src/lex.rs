@@ -0,0 +1,123 @@
+use core::ops::Range;
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum TokenKind<'a> {
+    Ident(&'a str),
+    Number(&'a str),
+    String(String),
+    Op(&'a str),
+    Newline,
+    Other(char),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Token<'a> {
+    pub kind: TokenKind<'a>,
+    /// Byte range of the token in the query string.
+    pub span: Range<usize>,
+}
+
+/// Words that start a dfsql statement.
+pub(crate) const STATEMENTS: &[&str] = &[
+    "select", "filter", "sort", "limit", "group", "reverse", "rename", "use",
+];
+
+/// Words of the dfsql language other than [`STATEMENTS`] that are never column names.
+pub(crate) const KEYWORDS: &[&str] = &[
+    "agg", "col", "alias", "as", "desc", "asc", "by", "and", "or", "not", "true", "false", "null",
+    "exists", "sum", "count", "mean", "median", "min", "max", "first", "last", "len", "abs",
+    "cast", "str", "int", "uint", "float", "bool",
+];
+
+/// Split a dfsql query into coarse tokens.
+///
+/// The lexer only understands enough of the language to find identifiers and literals; it never fails.
+pub(crate) fn lex(sql: &str) -> Vec<Token<'_>> {
+    let mut tokens = vec![];
+    let mut chars = sql.char_indices().peekable();
+    while let Some((start, c)) = chars.next() {
+        let kind = match c {
+            '\n' => TokenKind::Newline,
+            c if c.is_whitespace() => continue,
+            c if c.is_alphabetic() || c == '_' => {
+                let end = take_while(&mut chars, sql.len(), |c| c.is_alphanumeric() || c == '_');
+                TokenKind::Ident(&sql[start..end])
+            }
+            c if c.is_ascii_digit() => {
+                let end = take_while(&mut chars, sql.len(), |c| c.is_ascii_digit() || c == '.');
+                TokenKind::Number(&sql[start..end])
+            }
+            '"' => {
+                let mut s = String::new();
+                let mut escaped = false;
+                for (_, c) in chars.by_ref() {
+                    match c {
+                        _ if escaped => {
+                            s.push(c);
+                            escaped = false;
+                        }
+                        '\\' => escaped = true,
+                        '"' => break,
+                        _ => s.push(c),
+                    }
+                }
+                TokenKind::String(s)
+            }
+            c if is_op(c) => {
+                let end = take_while(&mut chars, sql.len(), is_op);
+                TokenKind::Op(&sql[start..end])
+            }
+            c => TokenKind::Other(c),
+        };
+        let end = chars.peek().map(|(i, _)| *i).unwrap_or(sql.len());
+        tokens.push(Token {
+            kind,
+            span: start..end,
+        });
+    }
+    tokens
+}
+
+fn is_op(c: char) -> bool {
+    "=<>!&|+-*/%".contains(c)
+}
+
+fn take_while(
+    chars: &mut core::iter::Peekable<core::str::CharIndices<'_>>,
+    len: usize,
+    f: impl Fn(char) -> bool,
+) -> usize {
+    while let Some((_, c)) = chars.peek() {
+        if !f(*c) {
+            break;
+        }
+        chars.next();
+    }
+    chars.peek().map(|(i, _)| *i).unwrap_or(len)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lex() {
+        let kinds = lex("filter x >= -1.5\nsort \"a b\"")
+            .into_iter()
+            .map(|t| t.kind)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            kinds,
+            [
+                TokenKind::Ident("filter"),
+                TokenKind::Ident("x"),
+                TokenKind::Op(">="),
+                TokenKind::Op("-"),
+                TokenKind::Number("1.5"),
+                TokenKind::Newline,
+                TokenKind::Ident("sort"),
+                TokenKind::String("a b".into()),
+            ]
+        );
+    }
+}
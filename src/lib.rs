@@ -1,20 +1,51 @@
 use std::sync::Arc;
 
+mod cache;
+pub mod capacity;
+pub mod complete;
+#[cfg(feature = "config")]
+pub mod config;
+pub mod diff;
+pub mod dynamic;
+pub mod format;
 #[cfg(feature = "hdv")]
-mod hdv;
+pub mod hdv;
+mod lex;
+pub mod options;
+mod plan;
 pub mod row;
+pub mod sample;
+#[cfg(feature = "serde")]
+pub mod serde;
+pub mod style;
 pub mod table;
 pub mod table_view;
+pub mod validate;
 
 type ArcStr = Arc<str>;
 
 #[cfg(test)]
 mod tests {
     use crate::{
-        row::{LiteralType, LiteralValue, TableRow, ValueDisplay},
+        row::{FieldVisitor, LiteralType, LiteralValue, TableRow, ValueDisplay},
         table::Table,
     };
 
+    /// A row of one integer column `x`.
+    struct XRow {
+        x: i64,
+    }
+    impl TableRow for XRow {
+        fn schema() -> Vec<(String, LiteralType)> {
+            vec![("x".to_string(), LiteralType::Int)]
+        }
+
+        fn fields(&self) -> Vec<Option<LiteralValue>> {
+            vec![Some(self.x.into())]
+        }
+    }
+    impl ValueDisplay for XRow {}
+
     #[test]
     fn test_i128() {
         struct Row {
@@ -91,4 +122,945 @@ mod tests {
 "
         );
     }
+    #[test]
+    fn test_filter_push_down() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        static FIELDS_CALLS: AtomicUsize = AtomicUsize::new(0);
+        struct Row {
+            x: i64,
+            y: String,
+        }
+        impl TableRow for Row {
+            fn schema() -> Vec<(String, LiteralType)> {
+                vec![
+                    ("x".to_string(), LiteralType::Int),
+                    ("y".to_string(), LiteralType::String),
+                ]
+            }
+
+            fn fields(&self) -> Vec<Option<LiteralValue>> {
+                FIELDS_CALLS.fetch_add(1, Ordering::Relaxed);
+                vec![Some(self.x.into()), Some(self.y.clone().into())]
+            }
+        }
+        impl ValueDisplay for Row {}
+
+        let table = Table::new();
+        let _a = table.set_scope(Row {
+            x: 0,
+            y: "a".to_string(),
+        });
+        let _b = table.set_scope(Row {
+            x: 1,
+            y: "b".to_string(),
+        });
+        let view = table.to_view("filter x = 1").unwrap();
+        assert_eq!(
+            view.to_string(),
+            "x y 
+1 b 
+"
+        );
+        // Each row is built once per query however many columns are filtered and selected
+        for sql in [
+            "",
+            "filter x = 1",
+            "filter x >= 0\nfilter y = \"b\"\nselect y x",
+        ] {
+            FIELDS_CALLS.store(0, Ordering::Relaxed);
+            table.to_view(sql).unwrap();
+            assert_eq!(FIELDS_CALLS.load(Ordering::Relaxed), 2, "{sql:?}");
+        }
+    }
+    #[test]
+    fn test_visit_fields() {
+        struct Row {
+            x: i64,
+            y: Option<String>,
+        }
+        impl TableRow for Row {
+            fn schema() -> Vec<(String, LiteralType)> {
+                vec![
+                    ("x".to_string(), LiteralType::Int),
+                    ("y".to_string(), LiteralType::String),
+                ]
+            }
+
+            fn fields(&self) -> Vec<Option<LiteralValue>> {
+                unreachable!()
+            }
+
+            fn visit_fields(&self, visitor: &mut dyn FieldVisitor) {
+                visitor.visit_int(0, self.x);
+                match &self.y {
+                    Some(y) => visitor.visit_str(1, y),
+                    None => visitor.visit_null(1),
+                }
+            }
+        }
+        impl ValueDisplay for Row {}
+
+        let table = Table::new();
+        let _a = table.set_scope(Row {
+            x: 1,
+            y: Some("a".to_string()),
+        });
+        let _b = table.set_scope(Row { x: 0, y: None });
+        let view = table.to_view("sort x").unwrap();
+        assert_eq!(
+            view.to_string(),
+            "x y 
+0   
+1 a 
+"
+        );
+        // Pushed down filters read the visited fields
+        let view = table.to_view("filter x = 1").unwrap();
+        assert_eq!(view.to_string(), "x y \n1 a \n");
+    }
+    #[test]
+    fn test_rich_types() {
+        use std::{
+            net::SocketAddr,
+            time::{Duration, SystemTime, UNIX_EPOCH},
+        };
+
+        struct Row {
+            started: SystemTime,
+            elapsed: Duration,
+            peer: SocketAddr,
+            sent: u64,
+        }
+        impl TableRow for Row {
+            fn schema() -> Vec<(String, LiteralType)> {
+                vec![
+                    ("started".to_string(), LiteralType::Timestamp),
+                    ("elapsed".to_string(), LiteralType::Duration),
+                    ("peer".to_string(), LiteralType::SocketAddr),
+                    ("sent".to_string(), LiteralType::ByteSize),
+                ]
+            }
+
+            fn fields(&self) -> Vec<Option<LiteralValue>> {
+                vec![
+                    Some(self.started.into()),
+                    Some(self.elapsed.into()),
+                    Some(self.peer.into()),
+                    Some(LiteralValue::ByteSize(self.sent)),
+                ]
+            }
+        }
+        impl ValueDisplay for Row {}
+
+        let table = Table::new();
+        let _a = table.set_scope(Row {
+            started: UNIX_EPOCH + Duration::from_secs(1),
+            elapsed: Duration::from_millis(1200),
+            peer: "127.0.0.1:80".parse().unwrap(),
+            sent: 3_565_158,
+        });
+        let _b = table.set_scope(Row {
+            started: UNIX_EPOCH,
+            elapsed: Duration::from_secs(61),
+            peer: "[::1]:8080".parse().unwrap(),
+            sent: 12,
+        });
+        let view = table.to_view("sort elapsed").unwrap();
+        assert_eq!(
+            view.to_string(),
+            "started                  elapsed peer         sent    
+1970-01-01T00:00:01.000Z    1.2s 127.0.0.1:80 3.4 MiB 
+1970-01-01T00:00:00.000Z    1m1s [::1]:8080      12 B 
+"
+        );
+
+        // Visitors written before the rich types receive them as the basic ones
+        struct Strings(Vec<String>);
+        impl FieldVisitor for Strings {
+            fn visit_null(&mut self, _: usize) {
+                self.0.push(String::new());
+            }
+            fn visit_str(&mut self, _: usize, value: &str) {
+                self.0.push(value.to_string());
+            }
+            fn visit_uint(&mut self, _: usize, value: u64) {
+                self.0.push(value.to_string());
+            }
+            fn visit_int(&mut self, _: usize, value: i64) {
+                self.0.push(value.to_string());
+            }
+            fn visit_float(&mut self, _: usize, value: f64) {
+                self.0.push(value.to_string());
+            }
+            fn visit_bool(&mut self, _: usize, value: bool) {
+                self.0.push(value.to_string());
+            }
+        }
+        let mut strings = Strings(vec![]);
+        let row = Row {
+            started: UNIX_EPOCH + Duration::from_secs(1),
+            elapsed: Duration::from_millis(1200),
+            peer: "127.0.0.1:80".parse().unwrap(),
+            sent: 12,
+        };
+        row.visit_fields(&mut strings);
+        assert_eq!(strings.0, ["1", "1.2", "127.0.0.1:80", "12"]);
+    }
+    #[test]
+    fn test_column_format() {
+        use crate::format::Format;
+
+        struct Row {
+            packets: u64,
+        }
+        impl TableRow for Row {
+            fn schema() -> Vec<(String, LiteralType)> {
+                vec![("packets".to_string(), LiteralType::UInt)]
+            }
+
+            fn fields(&self) -> Vec<Option<LiteralValue>> {
+                vec![Some(self.packets.into())]
+            }
+        }
+        impl ValueDisplay for Row {
+            fn column_format(header: &str) -> Option<Format> {
+                match header {
+                    "packets" => Some(Format::Thousands),
+                    _ => None,
+                }
+            }
+        }
+
+        let table = Table::new();
+        let _a = table.set_scope(Row { packets: 1234567 });
+        let view = table.to_view("").unwrap();
+        assert_eq!(
+            view.to_string(),
+            "packets   
+1,234,567 
+"
+        );
+    }
+    #[test]
+    fn test_float_options() {
+        use crate::options::{NonFinite, ViewOptions};
+
+        struct Row {
+            cpu: f64,
+            mem: f64,
+            load: f64,
+        }
+        impl TableRow for Row {
+            fn schema() -> Vec<(String, LiteralType)> {
+                vec![
+                    ("cpu".to_string(), LiteralType::Float),
+                    ("mem".to_string(), LiteralType::Float),
+                    ("load".to_string(), LiteralType::Float),
+                ]
+            }
+
+            fn fields(&self) -> Vec<Option<LiteralValue>> {
+                vec![
+                    Some(self.cpu.into()),
+                    Some(self.mem.into()),
+                    Some(self.load.into()),
+                ]
+            }
+        }
+        impl ValueDisplay for Row {
+            fn display_value(header: &str, value: Option<LiteralValue>) -> String {
+                match (header, value) {
+                    ("load", Some(LiteralValue::Float(v))) => format!("{v:.3}"),
+                    (_, value) => value.map(|v| v.to_string()).unwrap_or_default(),
+                }
+            }
+            fn custom_display(header: &str) -> bool {
+                header == "load"
+            }
+        }
+
+        let table = Table::new();
+        let _a = table.set_scope(Row {
+            cpu: 12.345678,
+            mem: 0.5,
+            load: 1.5,
+        });
+        let _b = table.set_scope(Row {
+            cpu: f64::NAN,
+            mem: f64::INFINITY,
+            load: 0.25,
+        });
+        let options = ViewOptions {
+            float_precision: Some(2),
+            column_float_precision: [("mem".to_string(), 0)].into(),
+            non_finite: NonFinite::Text("-".to_string()),
+            ..Default::default()
+        };
+        let view = table.to_view_with("", &options).unwrap();
+        assert_eq!(
+            view.to_string(),
+            "cpu   mem load  
+12.35   0 1.500 
+    -   - 0.250 
+"
+        );
+        // The precision of a column wins over the display of the row
+        let options = ViewOptions {
+            column_float_precision: [("mem".to_string(), 0), ("load".to_string(), 1)].into(),
+            ..options
+        };
+        let view = table.to_view_with("", &options).unwrap();
+        assert_eq!(
+            view.to_string(),
+            "cpu   mem load 
+12.35   0  1.5 
+    -   -  0.2 
+"
+        );
+    }
+    #[test]
+    fn test_bytes_and_list() {
+        use crate::format::Format;
+
+        struct Row {
+            id: Vec<u8>,
+            tags: Vec<&'static str>,
+        }
+        impl TableRow for Row {
+            fn schema() -> Vec<(String, LiteralType)> {
+                vec![
+                    ("id".to_string(), LiteralType::Bytes),
+                    ("raw".to_string(), LiteralType::Bytes),
+                    ("tags".to_string(), LiteralType::List),
+                ]
+            }
+
+            fn fields(&self) -> Vec<Option<LiteralValue>> {
+                let tags = self
+                    .tags
+                    .iter()
+                    .map(|t| Some(t.to_string().into()))
+                    .collect();
+                vec![
+                    Some(self.id.clone().into()),
+                    Some(self.id.clone().into()),
+                    Some(LiteralValue::List(tags)),
+                ]
+            }
+        }
+        impl ValueDisplay for Row {
+            fn column_format(header: &str) -> Option<Format> {
+                match header {
+                    "id" => Some(Format::Base64),
+                    _ => None,
+                }
+            }
+        }
+
+        let table = Table::new();
+        let _a = table.set_scope(Row {
+            id: vec![0, 255],
+            tags: vec!["a", "b"],
+        });
+        let view = table.to_view("").unwrap();
+        assert_eq!(
+            view.to_string(),
+            "id   raw  tags 
+AP8= 00ff a, b 
+"
+        );
+    }
+    #[test]
+    fn test_all_null_column_type() {
+        use crate::table_view::en::Alignment;
+
+        struct Row {
+            name: String,
+            x: Option<i64>,
+        }
+        impl TableRow for Row {
+            fn schema() -> Vec<(String, LiteralType)> {
+                vec![
+                    ("name".to_string(), LiteralType::String),
+                    ("x".to_string(), LiteralType::Int),
+                ]
+            }
+
+            fn fields(&self) -> Vec<Option<LiteralValue>> {
+                vec![Some(self.name.clone().into()), self.x.map(|x| x.into())]
+            }
+        }
+        impl ValueDisplay for Row {}
+
+        let table = Table::new();
+        let _a = table.set_scope(Row {
+            name: "a".to_string(),
+            x: None,
+        });
+        let view = table.to_view("").unwrap();
+        assert_eq!(view.alignments(), [Alignment::Left, Alignment::Right]);
+    }
+    #[test]
+    fn test_query_cache() {
+        use std::time::Duration;
+
+        let table = Table::new();
+        table.set_query_cache(Some(Duration::from_secs(3600)));
+        let a = table.set_scope(XRow { x: 0 });
+        let generation = table.generation();
+        assert_eq!(table.to_view("sort x").unwrap().to_string(), "x \n0 \n");
+
+        // Stale views are served within the max staleness
+        a.inspect_mut(|r| r.x = 1);
+        assert!(table.generation() > generation);
+        assert_eq!(table.to_view("sort x").unwrap().to_string(), "x \n0 \n");
+
+        table.set_query_cache(Some(Duration::ZERO));
+        assert_eq!(table.to_view("sort x").unwrap().to_string(), "x \n1 \n");
+        let _b = table.set_scope(XRow { x: 2 });
+        assert_eq!(table.to_view("sort x").unwrap().to_string(), "x \n1 \n2 \n");
+    }
+    #[test]
+    fn test_prepare() {
+        let table = Table::new();
+        let e = table.prepare("sort xx").unwrap_err();
+        assert!(e.to_string().contains("did you mean `x`?"));
+        assert!(
+            e.downcast_ref::<crate::validate::ValidationError>()
+                .is_some()
+        );
+        let query = table.prepare("sort x").unwrap();
+        assert_eq!(query.to_view().unwrap().to_string(), "x \n");
+
+        let _a = table.set_scope(XRow { x: 1 });
+        let _b = table.set_scope(XRow { x: 0 });
+        assert_eq!(query.to_view().unwrap().to_string(), "x \n0 \n1 \n");
+    }
+    #[test]
+    fn test_named_views() {
+        use crate::options::{NamedView, ViewOptions};
+
+        struct Row {
+            cpu: f64,
+        }
+        impl TableRow for Row {
+            fn schema() -> Vec<(String, LiteralType)> {
+                vec![("cpu".to_string(), LiteralType::Float)]
+            }
+
+            fn fields(&self) -> Vec<Option<LiteralValue>> {
+                vec![Some(self.cpu.into())]
+            }
+        }
+        impl ValueDisplay for Row {}
+
+        let table = Table::new();
+        let _a = table.set_scope(Row { cpu: 0.25 });
+        let _b = table.set_scope(Row { cpu: 0.5 });
+        table.register_view(
+            "top-cpu".to_string(),
+            NamedView {
+                sql: "sort cpu desc\nlimit 1".to_string(),
+                options: ViewOptions {
+                    float_precision: Some(1),
+                    ..Default::default()
+                },
+            },
+        );
+        table.register_view("all".to_string(), NamedView::new("".to_string()));
+
+        let names: Vec<String> = table.views().into_iter().map(|(name, _)| name).collect();
+        assert_eq!(names, ["all", "top-cpu"]);
+        let view = table.to_named_view("top-cpu").unwrap();
+        assert_eq!(view.to_string(), "cpu \n0.5 \n");
+        let e = table.to_named_view("top_cpu").unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            "Unknown view `top_cpu`, did you mean `top-cpu`? Available views: all, top-cpu"
+        );
+    }
+    #[test]
+    fn test_view_overrides() {
+        use crate::{
+            format::Format,
+            options::{NamedView, ViewOptions},
+            table_view::en::Alignment,
+        };
+
+        struct Row {
+            pid: u64,
+            name: String,
+            mem: u64,
+        }
+        impl TableRow for Row {
+            fn schema() -> Vec<(String, LiteralType)> {
+                vec![
+                    ("pid".to_string(), LiteralType::UInt),
+                    ("name".to_string(), LiteralType::String),
+                    ("mem".to_string(), LiteralType::UInt),
+                ]
+            }
+
+            fn fields(&self) -> Vec<Option<LiteralValue>> {
+                vec![
+                    Some(self.pid.into()),
+                    Some(self.name.clone().into()),
+                    Some(self.mem.into()),
+                ]
+            }
+        }
+        impl ValueDisplay for Row {}
+
+        let table = Table::new();
+        let _a = table.set_scope(Row {
+            pid: 1,
+            name: "a".to_string(),
+            mem: 2048,
+        });
+        let _b = table.set_scope(Row {
+            pid: 2,
+            name: "bb".to_string(),
+            mem: 1,
+        });
+        table.set_default_view(NamedView {
+            sql: "sort mem".to_string(),
+            options: ViewOptions {
+                column_formats: [("mem".to_string(), Format::BytesIec)].into(),
+                column_order: vec!["mem".to_string()],
+                hidden_columns: ["pid".to_string()].into(),
+                alignments: [("name".to_string(), Alignment::Right)].into(),
+                ..Default::default()
+            },
+        });
+        let view = table.to_view("").unwrap();
+        assert_eq!(
+            view.to_string(),
+            "mem     name 
+    1 B   bb 
+2.0 KiB    a 
+"
+        );
+        let view = table.to_view("sort pid").unwrap();
+        assert_eq!(view.alignments(), [Alignment::Right, Alignment::Right]);
+
+        let options = ViewOptions {
+            hidden_columns: ["pid", "name", "mem"].map(String::from).into(),
+            ..Default::default()
+        };
+        let view = table.to_view_with("", &options).unwrap();
+        assert_eq!(view.to_string(), "\n");
+        assert!(table.snapshot("", &options).unwrap().is_empty());
+    }
+    #[test]
+    fn test_style_rules() {
+        use crate::{
+            options::ViewOptions,
+            style::{Color, ColorChoice, Style, StyleRule},
+        };
+
+        struct Row {
+            cpu: i64,
+            name: &'static str,
+        }
+        impl TableRow for Row {
+            fn schema() -> Vec<(String, LiteralType)> {
+                vec![
+                    ("cpu".to_string(), LiteralType::Int),
+                    ("name".to_string(), LiteralType::String),
+                ]
+            }
+
+            fn fields(&self) -> Vec<Option<LiteralValue>> {
+                vec![
+                    Some(self.cpu.into()),
+                    Some(LiteralValue::String(self.name.into())),
+                ]
+            }
+        }
+        impl ValueDisplay for Row {}
+
+        let table = Table::new();
+        let _a = table.set_scope(Row {
+            cpu: 100,
+            name: "a",
+        });
+        let _b = table.set_scope(Row { cpu: 5, name: "b" });
+        let mut options = ViewOptions {
+            style_rules: vec![StyleRule::cell(
+                "cpu".to_string(),
+                |v| matches!(v, LiteralValue::Int(x) if 90 < *x),
+                Style::fg(Color::Red),
+            )],
+            color: ColorChoice::Always,
+            ..Default::default()
+        };
+        let view = table.to_view_with("sort cpu", &options).unwrap();
+        assert_eq!(
+            view.to_string(),
+            "cpu name \n  5 b    \n\x1b[31m100\x1b[0m a    \n"
+        );
+
+        options.color = ColorChoice::Never;
+        let view = table.to_view_with("sort cpu", &options).unwrap();
+        assert_eq!(view.to_string(), "cpu name \n  5 b    \n100 a    \n");
+
+        // Auto is decided by the destination when the view is written
+        options.color = ColorChoice::Auto;
+        let view = table.to_view_with("sort cpu", &options).unwrap();
+        assert_eq!(view.to_string(), "cpu name \n  5 b    \n100 a    \n");
+        if std::env::var_os("NO_COLOR").is_none() {
+            assert_eq!(
+                view.display_for(true).to_string(),
+                "cpu name \n  5 b    \n\x1b[31m100\x1b[0m a    \n"
+            );
+        }
+
+        // Rules read hidden columns
+        let options = ViewOptions {
+            style_rules: vec![StyleRule::row(
+                "cpu".to_string(),
+                |v| matches!(v, LiteralValue::Int(x) if 90 < *x),
+                Style::fg(Color::Red),
+            )],
+            color: ColorChoice::Always,
+            hidden_columns: ["cpu".to_string()].into(),
+            ..Default::default()
+        };
+        let view = table.to_view_with("sort cpu", &options).unwrap();
+        assert_eq!(view.to_string(), "name \nb    \n\x1b[31ma\x1b[0m    \n");
+    }
+    #[test]
+    fn test_meta_columns() {
+        use crate::{
+            dynamic::{DynamicSchema, DynamicTable},
+            options::{MetaColumn, ViewOptions},
+            table::RowKey,
+        };
+
+        let table = Table::new();
+        let _a = table.set_scope(XRow { x: 1 });
+        let b = table.set_scope(XRow { x: 2 });
+        let options = ViewOptions {
+            meta_columns: vec![MetaColumn::Key],
+            ..Default::default()
+        };
+        let id = b.key().id();
+        let view = table
+            .to_view_with(&format!("filter _key = {id}"), &options)
+            .unwrap();
+        let width = id.to_string().len();
+        assert_eq!(view.to_string(), format!("x {:width$} \n2 {id} \n", "_key"));
+        assert!(table.to_view("select _key").is_err());
+        assert!(table.prepare("select _key").is_err());
+        let query = table
+            .prepare_with("select _key", &[MetaColumn::Key, MetaColumn::Key])
+            .unwrap();
+        assert_eq!(query.to_view().unwrap().to_string().lines().count(), 3);
+        assert_eq!(
+            query
+                .to_view_with(&options)
+                .unwrap()
+                .to_string()
+                .lines()
+                .count(),
+            3
+        );
+        let idle = ViewOptions {
+            meta_columns: vec![MetaColumn::Idle],
+            ..Default::default()
+        };
+        assert!(query.to_view_with(&idle).is_err());
+
+        let key = RowKey::from_id(id);
+        assert_eq!(table.inspect(key, |r| r.x), Some(2));
+        assert_eq!(table.remove(key).map(|r| r.x), Some(2));
+        assert_eq!(table.inspect(key, |r| r.x), None);
+
+        // Meta columns cannot shadow columns of the table
+        let schema = DynamicSchema::new(vec![("_key".to_string(), LiteralType::UInt)]).unwrap();
+        let table = DynamicTable::new(schema);
+        assert!(table.to_view_with("", &options).is_err());
+    }
+    #[test]
+    fn test_age_and_idle() {
+        use crate::options::{MetaColumn, ViewOptions};
+
+        let table = Table::new();
+        table.set_query_cache(Some(std::time::Duration::ZERO));
+        let _a = table.set_scope(XRow { x: 1 });
+        let options = ViewOptions {
+            meta_columns: vec![MetaColumn::Age, MetaColumn::Idle],
+            ..Default::default()
+        };
+        let view = table
+            .to_view_with("filter _idle > 60\nselect x", &options)
+            .unwrap();
+        assert_eq!(view.to_string(), "x \n");
+        let view = table
+            .to_view_with("filter _age < 60\nselect x", &options)
+            .unwrap();
+        assert_eq!(view.to_string(), "x \n1 \n");
+
+        // The ages move on although the rows do not change
+        let age = || {
+            let view = table.to_view_with("select _age", &options).unwrap();
+            let cell = view.to_string().lines().nth(1).unwrap().trim().to_string();
+            let (number, unit) = cell.split_at(cell.find(|c: char| c.is_alphabetic()).unwrap());
+            let number: f64 = number.parse().unwrap();
+            let secs = match unit {
+                "ns" => number / 1e9,
+                "µs" => number / 1e6,
+                "ms" => number / 1e3,
+                "s" => number,
+                _ => panic!("unexpected duration `{cell}`"),
+            };
+            std::time::Duration::from_secs_f64(secs)
+        };
+        let first = age();
+        std::thread::sleep(std::time::Duration::from_millis(2));
+        let second = age();
+        assert!(first < second);
+        assert!(std::time::Duration::from_millis(2) <= second);
+    }
+    #[test]
+    fn test_capacity() {
+        use crate::capacity::{Capacity, CapacityError, CapacityPolicy};
+
+        let table = Table::new();
+        assert!(
+            table
+                .set_capacity(Some(Capacity::new(0, CapacityPolicy::EvictOldest)))
+                .is_err()
+        );
+        table
+            .set_capacity(Some(Capacity::new(
+                2,
+                CapacityPolicy::EvictLeastRecentlyUpdated,
+            )))
+            .unwrap();
+        let _a = table.set_scope(XRow { x: 1 });
+        let b = table.set_scope(XRow { x: 2 });
+        let e = table.try_insert(XRow { x: 3 }).unwrap_err();
+        assert_eq!(
+            e.downcast_ref::<CapacityError>(),
+            Some(&CapacityError { max_rows: 2 })
+        );
+
+        let step = 10;
+        assert_eq!(
+            b.inspect_mut(|r| {
+                r.x += step;
+                r.x
+            }),
+            Some(12)
+        );
+        let _c = table.set_scope(XRow { x: 3 });
+        assert_eq!(
+            table.to_view("sort x").unwrap().to_string(),
+            "x  \n 3 \n12 \n"
+        );
+
+        table
+            .set_capacity(Some(Capacity::new(1, CapacityPolicy::EvictOldest)))
+            .unwrap();
+        let _d = table.set_scope(XRow { x: 4 });
+        assert_eq!(b.inspect_mut(|r| r.x += step), None);
+        assert_eq!(table.to_view("").unwrap().to_string(), "x \n4 \n");
+        assert_eq!(
+            table
+                .stats_view("select evicted rejected")
+                .unwrap()
+                .to_string(),
+            "evicted rejected \n      3        1 \n"
+        );
+        // Removed rows leave the eviction order too
+        drop(_d);
+        let _e = table.set_scope(XRow { x: 5 });
+        assert_eq!(table.to_view("").unwrap().to_string(), "x \n5 \n");
+        assert_eq!(table.stats().evicted, 3);
+    }
+    #[cfg(feature = "hdv")]
+    #[test]
+    fn test_hdv_snapshot() {
+        use hdv::{
+            format::{AtomScheme, AtomType, AtomValue, ObjectScheme},
+            serde::{HdvDeserialize, HdvScheme, HdvSerialize},
+        };
+
+        #[derive(Debug, PartialEq)]
+        struct Conn {
+            id: u64,
+            host: Option<String>,
+        }
+        impl HdvScheme for Conn {
+            fn object_scheme() -> ObjectScheme {
+                ObjectScheme {
+                    fields: vec![
+                        AtomScheme {
+                            name: "id".to_string(),
+                            r#type: AtomType::U64,
+                        },
+                        AtomScheme {
+                            name: "host".to_string(),
+                            r#type: AtomType::String,
+                        },
+                    ],
+                }
+            }
+        }
+        impl HdvSerialize for Conn {
+            fn serialize(&self, values: &mut Vec<Option<AtomValue>>) {
+                values.push(Some(AtomValue::U64(self.id)));
+                values.push(self.host.as_deref().map(|h| AtomValue::String(h.into())));
+            }
+        }
+        impl HdvDeserialize for Conn {
+            fn deserialize(values: &[Option<AtomValue>]) -> Self {
+                let id = match &values[0] {
+                    Some(AtomValue::U64(id)) => *id,
+                    _ => panic!("id is not an unsigned integer"),
+                };
+                let host = match &values[1] {
+                    Some(AtomValue::String(host)) => Some(host.to_string()),
+                    _ => None,
+                };
+                Self { id, host }
+            }
+        }
+        impl ValueDisplay for Conn {}
+
+        let table = Table::new();
+        let _a = table.set_scope(Conn {
+            id: 1,
+            host: Some("a".to_string()),
+        });
+        let _b = table.set_scope(Conn { id: 2, host: None });
+        let mut file = vec![];
+        table.dump_hdv(&mut file).unwrap();
+
+        let loaded = Table::<Conn>::load_hdv(&file[..]).unwrap();
+        assert_eq!(loaded.stats().rows, 2);
+        assert_eq!(
+            loaded.to_view("sort id").unwrap().to_string(),
+            table.to_view("sort id").unwrap().to_string()
+        );
+        assert_eq!(
+            loaded.to_view("filter id = 2").unwrap().to_string(),
+            "id host \n 2      \n"
+        );
+    }
+    #[test]
+    fn test_sampling() {
+        use crate::{
+            options::ViewOptions,
+            sample::Sampling,
+            table_view::{TableView, en::TableViewWrite},
+        };
+
+        let table = Table::new();
+        let _rows: Vec<_> = (0..100).map(|x| table.set_scope(XRow { x })).collect();
+        let options = ViewOptions {
+            sampling: Some(Sampling {
+                seed: Some(7),
+                ..Sampling::reservoir(3)
+            }),
+            ..Default::default()
+        };
+        let view = table.to_view_with("sort x", &options).unwrap();
+        assert_eq!(view.to_string().lines().count(), 4);
+        let sample = view.sample().unwrap();
+        assert_eq!((sample.sampled, sample.total), (3, 100));
+        assert_eq!(sample.to_string(), "≈ sampled 3 of 100 rows");
+        // The footer is only written out, so the rendered view parses back
+        let parsed = view.to_string().parse::<TableView>().unwrap();
+        let parsed = TableViewWrite::new(parsed, view.alignments().into()).unwrap();
+        assert_eq!(parsed.to_string(), view.to_string());
+        let path =
+            std::env::temp_dir().join(format!("monitor_table_sample_{}", std::process::id()));
+        view.write_to(std::fs::File::create(&path).unwrap())
+            .unwrap();
+        let written = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(written, format!("{view}{sample}\n"));
+        assert_eq!(
+            view.to_string(),
+            table.to_view_with("sort x", &options).unwrap().to_string()
+        );
+        let view = table.to_view_with("filter x >= 0", &options).unwrap();
+        assert_eq!(view.to_string().lines().count(), 4);
+        let options = ViewOptions {
+            sampling: Some(Sampling::uniform(f64::NAN)),
+            ..Default::default()
+        };
+        assert!(table.to_view_with("", &options).is_err());
+    }
+    #[test]
+    fn test_sampled_counts() {
+        use crate::{options::ViewOptions, sample::Sampling};
+
+        struct Row {
+            state: &'static str,
+            count: i64,
+        }
+        impl TableRow for Row {
+            fn schema() -> Vec<(String, LiteralType)> {
+                vec![
+                    ("state".to_string(), LiteralType::String),
+                    ("count".to_string(), LiteralType::Int),
+                ]
+            }
+
+            fn fields(&self) -> Vec<Option<LiteralValue>> {
+                vec![Some(self.state.to_string().into()), Some(self.count.into())]
+            }
+        }
+        impl ValueDisplay for Row {}
+
+        let table = Table::new();
+        let _rows: Vec<_> = (0..100)
+            .map(|i| {
+                table.set_scope(Row {
+                    state: ["running", "sleeping"][i % 2],
+                    count: 1,
+                })
+            })
+            .collect();
+        let options = ViewOptions {
+            sampling: Some(Sampling {
+                seed: Some(7),
+                ..Sampling::reservoir(10)
+            }),
+            ..Default::default()
+        };
+        // Row counts are scaled up from 10 rows to 100
+        let view = table
+            .to_view_with("group state agg count alias n\nsort state", &options)
+            .unwrap();
+        let s = view.to_string();
+        let mut lines = s.lines();
+        assert_eq!(lines.next().unwrap().split_whitespace().nth(1), Some("≈n"));
+        let total: u64 = lines
+            .take(2)
+            .map(|line| {
+                line.split_whitespace()
+                    .nth(1)
+                    .unwrap()
+                    .parse::<u64>()
+                    .unwrap()
+            })
+            .sum();
+        assert_eq!(total, 100);
+        // The mean of a source column named `count` is left as computed on the sample
+        let view = table
+            .to_view_with("group state agg mean count\nsort state", &options)
+            .unwrap();
+        let s = view.to_string();
+        let mut lines = s.lines();
+        assert_eq!(
+            lines.next().unwrap().split_whitespace().nth(1),
+            Some("count")
+        );
+        assert_eq!(lines.next().unwrap().split_whitespace().nth(1), Some("1"));
+    }
 }
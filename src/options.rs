@@ -0,0 +1,153 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::{
+    format::Format,
+    row::LiteralType,
+    sample::Sampling,
+    style::{ColorChoice, StyleRule},
+    table_view::en::{self, Alignment},
+};
+
+/// Display settings applied by [`crate::table::Table::to_view_with`] on top of [`crate::row::ValueDisplay`].
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct ViewOptions {
+    /// Decimal places of float cells in every column.
+    ///
+    /// Columns for which [`crate::row::ValueDisplay::custom_display`] is true are left as they are.
+    pub float_precision: Option<usize>,
+    /// Decimal places of float cells per column, overriding `float_precision` and the display of the row.
+    pub column_float_precision: HashMap<String, usize>,
+    /// How to show NaN and infinities.
+    pub non_finite: NonFinite,
+    /// Formats per column, overriding [`crate::row::ValueDisplay::column_format`].
+    pub column_formats: HashMap<String, Format>,
+    /// Columns to show first in this order; the other columns follow in the order of the query.
+    pub column_order: Vec<String>,
+    pub hidden_columns: HashSet<String>,
+    /// Alignments per column, overriding the alignment derived from the column type.
+    pub alignments: HashMap<String, Alignment>,
+    /// Rules evaluated on the values of every row to color its cells.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub style_rules: Vec<StyleRule>,
+    pub color: ColorChoice,
+    /// Columns describing the rows themselves, appended after the columns of the schema.
+    pub meta_columns: Vec<MetaColumn>,
+    /// Query a random subset of the rows to keep large tables responsive.
+    pub sampling: Option<Sampling>,
+}
+impl ViewOptions {
+    /// Return the decimal places of the column, leaving out the view-wide precision if the row displays the column itself.
+    pub(crate) fn float_precision(&self, header: &str, custom_display: bool) -> Option<usize> {
+        self.column_float_precision
+            .get(header)
+            .copied()
+            .or(self.float_precision.filter(|_| !custom_display))
+    }
+
+    /// Return `meta_columns` without repeats.
+    pub(crate) fn meta_columns(&self) -> Vec<MetaColumn> {
+        let mut columns: Vec<MetaColumn> = vec![];
+        for &column in &self.meta_columns {
+            if !columns.contains(&column) {
+                columns.push(column);
+            }
+        }
+        columns
+    }
+
+    /// Return the indices of the visible `headers` in display order.
+    pub(crate) fn column_order(&self, headers: &[String]) -> Vec<usize> {
+        en::column_order(headers, &self.column_order, &self.hidden_columns)
+    }
+}
+
+/// A column the table keeps for every row, named with a leading underscore.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum MetaColumn {
+    /// `_key`: the id of [`crate::table::RowKey`], stable for the lifetime of the row.
+    Key,
+    /// `_inserted_at`: when the row was inserted.
+    InsertedAt,
+    /// `_updated_at`: when the row was inserted or last mutated.
+    UpdatedAt,
+    /// `_age`: time since the row was inserted, in seconds to dfsql, e.g. `filter _age > 60`.
+    Age,
+    /// `_idle`: time since the row was inserted or last mutated, in seconds to dfsql.
+    Idle,
+}
+impl MetaColumn {
+    pub const ALL: &[MetaColumn] = &[
+        Self::Key,
+        Self::InsertedAt,
+        Self::UpdatedAt,
+        Self::Age,
+        Self::Idle,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Key => "_key",
+            Self::InsertedAt => "_inserted_at",
+            Self::UpdatedAt => "_updated_at",
+            Self::Age => "_age",
+            Self::Idle => "_idle",
+        }
+    }
+
+    pub fn literal_type(&self) -> LiteralType {
+        match self {
+            Self::Key => LiteralType::UInt,
+            Self::InsertedAt | Self::UpdatedAt => LiteralType::Timestamp,
+            Self::Age | Self::Idle => LiteralType::Duration,
+        }
+    }
+
+    /// Whether the value changes with the clock even if the row does not.
+    pub fn is_time_dependent(&self) -> bool {
+        matches!(self, Self::Age | Self::Idle)
+    }
+}
+
+/// How to show NaN and infinities in float cells.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum NonFinite {
+    /// `NaN`, `inf` and `-inf`.
+    #[default]
+    Show,
+    /// An empty cell like a null.
+    Blank,
+    /// A fixed text, e.g. `-`.
+    Text(String),
+}
+impl NonFinite {
+    /// Return `None` to leave the value to the usual rendering.
+    pub(crate) fn display(&self) -> Option<String> {
+        match self {
+            NonFinite::Show => None,
+            NonFinite::Blank => Some(String::new()),
+            NonFinite::Text(s) => Some(s.clone()),
+        }
+    }
+}
+
+/// A query saved on a table under a name together with how to display it.
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct NamedView {
+    pub sql: String,
+    #[cfg_attr(feature = "serde", serde(flatten))]
+    pub options: ViewOptions,
+}
+impl NamedView {
+    pub fn new(sql: String) -> Self {
+        Self {
+            sql,
+            options: ViewOptions::default(),
+        }
+    }
+}
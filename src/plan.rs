@@ -0,0 +1,439 @@
+use core::cmp::Ordering;
+
+use crate::{
+    lex::{KEYWORDS, STATEMENTS, Token, TokenKind, lex},
+    row::{LiteralType, LiteralValue},
+    table::timestamp_to_secs,
+};
+
+/// What `Table::to_view` needs to extract from the rows before handing them to dfsql.
+///
+/// The plan is only an optimization: dfsql still runs the full query on the extracted frame.
+#[derive(Debug, Clone)]
+pub(crate) struct QueryPlan {
+    /// Indices of the schema columns the query can observe; `None` if it can observe all of them.
+    columns: Option<Vec<usize>>,
+    /// Leading filters that can be evaluated on the rows directly.
+    filters: Vec<Filter>,
+    /// Key columns of the `group` statement; `None` if the query does not aggregate.
+    group_keys: Option<Vec<String>>,
+    /// Output names of the row counts of the `group` statement.
+    count_columns: Vec<String>,
+}
+impl QueryPlan {
+    pub fn new(sql: &str, schema: &[(String, LiteralType)]) -> Self {
+        let tokens = lex(sql);
+        Self {
+            columns: needed_columns(&tokens, schema),
+            filters: leading_filters(&tokens, schema),
+            group_keys: group_keys(&tokens),
+            count_columns: count_columns(&tokens),
+        }
+    }
+
+    pub fn columns(&self) -> Option<&[usize]> {
+        self.columns.as_deref()
+    }
+
+    pub fn filters(&self) -> &[Filter] {
+        &self.filters
+    }
+
+    pub fn group_keys(&self) -> Option<&[String]> {
+        self.group_keys.as_deref()
+    }
+
+    pub fn count_columns(&self) -> &[String] {
+        &self.count_columns
+    }
+}
+
+fn group_line<'t, 'a>(tokens: &'t [Token<'a>]) -> Option<&'t [Token<'a>]> {
+    tokens
+        .split(|t| t.kind == TokenKind::Newline)
+        .find(|line| line.first().map(|t| &t.kind) == Some(&TokenKind::Ident("group")))
+}
+
+/// Collect the columns named between `group` and `agg`.
+fn group_keys(tokens: &[Token<'_>]) -> Option<Vec<String>> {
+    let line = group_line(tokens)?;
+    let keys = line[1..]
+        .iter()
+        .take_while(|t| t.kind != TokenKind::Ident("agg"))
+        .filter_map(|t| match &t.kind {
+            TokenKind::Ident(s) if !KEYWORDS.contains(s) => Some(s.to_string()),
+            TokenKind::String(s) => Some(s.clone()),
+            _ => None,
+        })
+        .collect();
+    Some(keys)
+}
+
+/// Collect the names of the `count` and `len` aggregates after `agg`, skipping those passed to another function, e.g. `mean count`.
+fn count_columns(tokens: &[Token<'_>]) -> Vec<String> {
+    let Some(line) = group_line(tokens) else {
+        return vec![];
+    };
+    let Some(agg) = line.iter().position(|t| t.kind == TokenKind::Ident("agg")) else {
+        return vec![];
+    };
+    let aggregates = &line[agg + 1..];
+    let mut columns = vec![];
+    for (i, token) in aggregates.iter().enumerate() {
+        let TokenKind::Ident(word @ ("count" | "len")) = token.kind else {
+            continue;
+        };
+        let previous = aggregates[..i]
+            .iter()
+            .rev()
+            .find(|t| t.kind != TokenKind::Other('('));
+        // A keyword before the word either takes it as an argument or names a column after it
+        if let Some(Token {
+            kind: TokenKind::Ident(previous),
+            ..
+        }) = previous
+            && KEYWORDS.contains(previous)
+        {
+            continue;
+        }
+        let next = aggregates[i + 1..]
+            .iter()
+            .find(|t| t.kind != TokenKind::Other(')'));
+        let alias = match next.map(|t| &t.kind) {
+            Some(TokenKind::Ident("alias" | "as")) => aggregates[i + 1..]
+                .iter()
+                .skip_while(|t| !matches!(t.kind, TokenKind::Ident("alias" | "as")))
+                .nth(1),
+            _ => None,
+        };
+        let name = match alias.map(|t| &t.kind) {
+            Some(TokenKind::Ident(name)) => name.to_string(),
+            Some(TokenKind::String(name)) => name.clone(),
+            _ => word.to_string(),
+        };
+        columns.push(name);
+    }
+    columns
+}
+
+/// Columns are only prunable if the query projects them away with `select` or `group`.
+fn needed_columns(tokens: &[Token<'_>], schema: &[(String, LiteralType)]) -> Option<Vec<usize>> {
+    let projects = tokens
+        .iter()
+        .any(|t| matches!(t.kind, TokenKind::Ident("select" | "group")));
+    let wildcard = tokens.iter().any(|t| t.kind == TokenKind::Op("*"));
+    if !projects || wildcard {
+        return None;
+    }
+    let columns = schema
+        .iter()
+        .enumerate()
+        .filter(|(_, (header, _))| {
+            tokens.iter().any(|t| match &t.kind {
+                TokenKind::Ident(s) => s == header,
+                TokenKind::String(s) => s == header,
+                _ => false,
+            })
+        })
+        .map(|(i, _)| i)
+        .collect::<Vec<usize>>();
+    // A frame without columns would also lose the row count
+    if columns.is_empty() {
+        return None;
+    }
+    Some(columns)
+}
+
+/// Collect the `filter <column> <op> <literal>` statements at the start of the query.
+fn leading_filters(tokens: &[Token<'_>], schema: &[(String, LiteralType)]) -> Vec<Filter> {
+    let mut filters = vec![];
+    let mut lines = tokens
+        .split(|t| t.kind == TokenKind::Newline)
+        .filter(|line| !line.is_empty())
+        .peekable();
+    while let Some(line) = lines.next() {
+        let Some(filter) = Filter::parse(line, schema) else {
+            break;
+        };
+        // Bail out if the next line could be a continuation of the predicate instead of a new statement
+        if let Some(next) = lines.peek()
+            && !matches!(next[0].kind, TokenKind::Ident(word) if STATEMENTS.contains(&word))
+        {
+            break;
+        }
+        filters.push(filter);
+    }
+    filters
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct Filter {
+    column: usize,
+    op: CmpOp,
+    literal: Literal,
+}
+impl Filter {
+    fn parse(line: &[Token<'_>], schema: &[(String, LiteralType)]) -> Option<Self> {
+        let (column, op, literal) = match line {
+            [filter, column, op, literal @ ..] if filter.kind == TokenKind::Ident("filter") => {
+                (column, op, literal)
+            }
+            _ => return None,
+        };
+        let column_name = match &column.kind {
+            TokenKind::Ident(s) => *s,
+            _ => return None,
+        };
+        let column = schema.iter().position(|(h, _)| h == column_name)?;
+        let op = match op.kind {
+            TokenKind::Op("=" | "==") => CmpOp::Eq,
+            TokenKind::Op("!=") => CmpOp::Ne,
+            TokenKind::Op("<") => CmpOp::Lt,
+            TokenKind::Op("<=") => CmpOp::Le,
+            TokenKind::Op(">") => CmpOp::Gt,
+            TokenKind::Op(">=") => CmpOp::Ge,
+            _ => return None,
+        };
+        let literal = Literal::parse(literal)?;
+        if !literal.is_comparable_to(schema[column].1) {
+            return None;
+        }
+        Some(Self {
+            column,
+            op,
+            literal,
+        })
+    }
+
+    pub fn column(&self) -> usize {
+        self.column
+    }
+
+    /// Return `false` only if dfsql would definitely drop the row.
+    pub fn matches(&self, value: Option<&LiteralValue>) -> bool {
+        let Some(value) = value else {
+            return false;
+        };
+        let ord = match (value, &self.literal) {
+            (LiteralValue::String(v), Literal::String(l)) => v.as_ref().cmp(l.as_str()),
+            (LiteralValue::UInt(v) | LiteralValue::ByteSize(v), Literal::Int(l)) => {
+                i128::from(*v).cmp(l)
+            }
+            (LiteralValue::Int(v), Literal::Int(l)) => i128::from(*v).cmp(l),
+            (
+                LiteralValue::Float(_) | LiteralValue::Duration(_) | LiteralValue::Timestamp(_),
+                Literal::Int(_) | Literal::Float(_),
+            ) => {
+                let (Some(v), Some(l)) = (frame_float(value), self.literal.as_f64()) else {
+                    return true;
+                };
+                match v.partial_cmp(&l) {
+                    Some(ord) => ord,
+                    None => return true,
+                }
+            }
+            (LiteralValue::Bool(v), Literal::Bool(l)) => match self.op {
+                CmpOp::Eq | CmpOp::Ne => v.cmp(l),
+                _ => return true,
+            },
+            _ => return true,
+        };
+        self.op.eval(ord)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum CmpOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+impl CmpOp {
+    fn eval(self, ord: Ordering) -> bool {
+        match self {
+            CmpOp::Eq => ord.is_eq(),
+            CmpOp::Ne => ord.is_ne(),
+            CmpOp::Lt => ord.is_lt(),
+            CmpOp::Le => ord.is_le(),
+            CmpOp::Gt => ord.is_gt(),
+            CmpOp::Ge => ord.is_ge(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Literal {
+    String(String),
+    Int(i128),
+    Float(f64),
+    Bool(bool),
+}
+impl Literal {
+    fn parse(tokens: &[Token<'_>]) -> Option<Self> {
+        let (negative, tokens) = match tokens {
+            [minus, rest @ ..] if minus.kind == TokenKind::Op("-") => (true, rest),
+            _ => (false, tokens),
+        };
+        let [token] = tokens else {
+            return None;
+        };
+        let literal = match &token.kind {
+            TokenKind::String(s) if !negative => Self::String(s.clone()),
+            TokenKind::Ident("true") if !negative => Self::Bool(true),
+            TokenKind::Ident("false") if !negative => Self::Bool(false),
+            TokenKind::Number(n) => match n.parse::<i128>() {
+                Ok(n) => Self::Int(if negative { -n } else { n }),
+                Err(_) => {
+                    let n = n.parse::<f64>().ok()?;
+                    Self::Float(if negative { -n } else { n })
+                }
+            },
+            _ => return None,
+        };
+        Some(literal)
+    }
+
+    /// Timestamps and durations are compared as seconds and byte sizes as bytes, as they are in the frame.
+    fn is_comparable_to(&self, ty: LiteralType) -> bool {
+        matches!(
+            (self, ty),
+            (Self::String(_), LiteralType::String)
+                | (
+                    Self::Int(_),
+                    LiteralType::UInt
+                        | LiteralType::Int
+                        | LiteralType::Float
+                        | LiteralType::Timestamp
+                        | LiteralType::Duration
+                        | LiteralType::ByteSize
+                )
+                | (
+                    Self::Float(_),
+                    LiteralType::Float | LiteralType::Timestamp | LiteralType::Duration
+                )
+                | (Self::Bool(_), LiteralType::Bool)
+        )
+    }
+
+    fn as_f64(&self) -> Option<f64> {
+        match self {
+            Self::Int(l) => Some(*l as f64),
+            Self::Float(l) => Some(*l),
+            _ => None,
+        }
+    }
+}
+
+/// Return the float dfsql sees for `value`.
+fn frame_float(value: &LiteralValue) -> Option<f64> {
+    match value {
+        LiteralValue::Float(v) => Some(*v),
+        LiteralValue::Duration(v) => Some(v.as_secs_f64()),
+        LiteralValue::Timestamp(v) => Some(timestamp_to_secs(*v)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn schema() -> Vec<(String, LiteralType)> {
+        vec![
+            ("name".to_string(), LiteralType::String),
+            ("cpu".to_string(), LiteralType::Float),
+            ("pid".to_string(), LiteralType::UInt),
+        ]
+    }
+
+    #[test]
+    fn test_columns() {
+        let schema = schema();
+        assert_eq!(QueryPlan::new("sort cpu", &schema).columns(), None);
+        assert_eq!(
+            QueryPlan::new("filter cpu > 1\nselect name", &schema).columns(),
+            Some([0, 1].as_slice())
+        );
+    }
+
+    #[test]
+    fn test_group_keys() {
+        let schema = schema();
+        assert_eq!(QueryPlan::new("sort cpu", &schema).group_keys(), None);
+        let plan = QueryPlan::new("filter cpu > 1\ngroup name agg count", &schema);
+        assert_eq!(plan.group_keys(), Some(["name".to_string()].as_slice()));
+    }
+
+    #[test]
+    fn test_count_columns() {
+        let schema = schema();
+        let counts = |sql| QueryPlan::new(sql, &schema).count_columns().to_vec();
+        assert!(counts("select count").is_empty());
+        assert_eq!(counts("group name agg count"), ["count"]);
+        assert_eq!(counts("group name agg (mean cpu) len"), ["len"]);
+        assert_eq!(counts("group name agg count alias n"), ["n"]);
+        assert_eq!(counts("group name agg (count) as \"rows\""), ["rows"]);
+        // Aggregates of a column named like a count are no row counts
+        assert!(counts("group name agg mean count").is_empty());
+        assert!(counts("group name agg (sum (col len))").is_empty());
+        assert!(counts("group name agg (mean cpu) alias count").is_empty());
+    }
+
+    #[test]
+    fn test_filters() {
+        let schema = schema();
+        let plan = QueryPlan::new("filter pid = 1\nfilter cpu >= -0.5\nsort cpu", &schema);
+        assert_eq!(plan.filters().len(), 2);
+        let pid = plan.filters()[0].clone();
+        assert_eq!(pid.column(), 2);
+        assert!(pid.matches(Some(&LiteralValue::UInt(1))));
+        assert!(!pid.matches(Some(&LiteralValue::UInt(2))));
+        assert!(!pid.matches(None));
+        let cpu = plan.filters()[1].clone();
+        assert!(cpu.matches(Some(&LiteralValue::Float(-0.5))));
+        assert!(!cpu.matches(Some(&LiteralValue::Float(-1.0))));
+
+        // The predicate might continue on the next line
+        let plan = QueryPlan::new("filter pid = 1\n| pid = 2", &schema);
+        assert!(plan.filters().is_empty());
+        let plan = QueryPlan::new("filter pid = 1\nor pid = 2", &schema);
+        assert!(plan.filters().is_empty());
+        let plan = QueryPlan::new("filter pid = 1\nfilter cpu > 1\nand name = \"a\"", &schema);
+        assert_eq!(plan.filters().len(), 1);
+        // Type mismatch is left to dfsql
+        let plan = QueryPlan::new("filter name = 1", &schema);
+        assert!(plan.filters().is_empty());
+        // Filters after other statements are not pushed down
+        let plan = QueryPlan::new("limit 1\nfilter pid = 1", &schema);
+        assert!(plan.filters().is_empty());
+    }
+
+    #[test]
+    fn test_filters_on_units() {
+        use std::time::{Duration, UNIX_EPOCH};
+
+        let schema = vec![
+            ("_idle".to_string(), LiteralType::Duration),
+            ("at".to_string(), LiteralType::Timestamp),
+            ("size".to_string(), LiteralType::ByteSize),
+        ];
+        let plan = QueryPlan::new(
+            "filter _idle > 60\nfilter at < 1.5\nfilter size >= 1024",
+            &schema,
+        );
+        assert_eq!(plan.filters().len(), 3);
+        let idle = &plan.filters()[0];
+        assert!(idle.matches(Some(&LiteralValue::Duration(Duration::from_secs(61)))));
+        assert!(!idle.matches(Some(&LiteralValue::Duration(Duration::from_secs(60)))));
+        let at = &plan.filters()[1];
+        let secs = |s| LiteralValue::Timestamp(UNIX_EPOCH + Duration::from_secs_f64(s));
+        assert!(at.matches(Some(&secs(1.))));
+        assert!(!at.matches(Some(&secs(2.))));
+        let size = &plan.filters()[2];
+        assert!(size.matches(Some(&LiteralValue::ByteSize(1024))));
+        assert!(!size.matches(Some(&LiteralValue::ByteSize(1023))));
+    }
+}
@@ -1,32 +1,138 @@
 use core::fmt;
+use std::{
+    net::{IpAddr, SocketAddr},
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
 
-use crate::ArcStr;
+use crate::{
+    ArcStr,
+    format::{self, Format},
+    table::timestamp_to_secs,
+};
 
 pub trait TableRow {
     /// Return all the header and the value type.
     fn schema() -> Vec<(String, LiteralType)>;
     /// Return all of the values corresponding to the schema.
     fn fields(&self) -> Vec<Option<LiteralValue>>;
+    /// Feed all of the values corresponding to the schema to `visitor`.
+    ///
+    /// Override this to skip the intermediate allocations of [`TableRow::fields`].
+    fn visit_fields(&self, visitor: &mut dyn FieldVisitor) {
+        for (i, value) in self.fields().into_iter().enumerate() {
+            visitor.visit(i, value);
+        }
+    }
+    /// Feed all of the values to `visitor`, or return why the row cannot produce them.
+    ///
+    /// Queries read every row once through this method and fail on an error instead of showing the row.
+    fn try_visit_fields(&self, visitor: &mut dyn FieldVisitor) -> anyhow::Result<()> {
+        self.visit_fields(visitor);
+        Ok(())
+    }
+}
+
+/// Receive the values of a row by their indices in the schema.
+pub trait FieldVisitor {
+    fn visit_null(&mut self, index: usize);
+    fn visit_str(&mut self, index: usize, value: &str);
+    fn visit_uint(&mut self, index: usize, value: u64);
+    fn visit_int(&mut self, index: usize, value: i64);
+    fn visit_float(&mut self, index: usize, value: f64);
+    fn visit_bool(&mut self, index: usize, value: bool);
+    /// Default to the seconds since the Unix epoch.
+    fn visit_timestamp(&mut self, index: usize, value: SystemTime) {
+        self.visit_float(index, timestamp_to_secs(value));
+    }
+    /// Default to the seconds.
+    fn visit_duration(&mut self, index: usize, value: Duration) {
+        self.visit_float(index, value.as_secs_f64());
+    }
+    fn visit_ip_addr(&mut self, index: usize, value: IpAddr) {
+        self.visit_str(index, &value.to_string());
+    }
+    fn visit_socket_addr(&mut self, index: usize, value: SocketAddr) {
+        self.visit_str(index, &value.to_string());
+    }
+    /// Default to the bytes.
+    fn visit_byte_size(&mut self, index: usize, value: u64) {
+        self.visit_uint(index, value);
+    }
+    /// Default to the hex string.
+    fn visit_bytes(&mut self, index: usize, value: &[u8]) {
+        self.visit_str(index, &format::hex(value));
+    }
+    /// Default to the items joined by commas.
+    fn visit_list(&mut self, index: usize, value: &[Option<LiteralValue>]) {
+        self.visit_str(index, &LiteralValue::List(value.into()).to_string());
+    }
+
+    fn visit(&mut self, index: usize, value: Option<LiteralValue>) {
+        let Some(value) = value else {
+            self.visit_null(index);
+            return;
+        };
+        match value {
+            LiteralValue::String(v) => self.visit_str(index, &v),
+            LiteralValue::UInt(v) => self.visit_uint(index, v),
+            LiteralValue::Int(v) => self.visit_int(index, v),
+            LiteralValue::Float(v) => self.visit_float(index, v),
+            LiteralValue::Bool(v) => self.visit_bool(index, v),
+            LiteralValue::Timestamp(v) => self.visit_timestamp(index, v),
+            LiteralValue::Duration(v) => self.visit_duration(index, v),
+            LiteralValue::IpAddr(v) => self.visit_ip_addr(index, v),
+            LiteralValue::SocketAddr(v) => self.visit_socket_addr(index, v),
+            LiteralValue::ByteSize(v) => self.visit_byte_size(index, v),
+            LiteralValue::Bytes(v) => self.visit_bytes(index, &v),
+            LiteralValue::List(v) => self.visit_list(index, &v),
+        }
+    }
 }
 
 pub trait ValueDisplay {
     /// Convert the value to a user-friendly one.
     fn display_value(header: &str, value: Option<LiteralValue>) -> String {
-        let _ = header;
-        match value {
-            Some(v) => v.to_string(),
-            None => String::new(),
+        let Some(value) = value else {
+            return String::new();
+        };
+        if let Some(s) = Self::column_format(header).and_then(|f| f.format(&value)) {
+            return s;
         }
+        value.to_string()
+    }
+    /// Return the format used by [`ValueDisplay::display_value`] for the column.
+    fn column_format(header: &str) -> Option<Format> {
+        let _ = header;
+        None
+    }
+    /// Return whether [`ValueDisplay::display_value`] formats the column itself.
+    ///
+    /// Override this along with a custom `display_value` to keep the view-wide float precision off the column.
+    fn custom_display(header: &str) -> bool {
+        Self::column_format(header).is_some()
     }
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum LiteralType {
     String,
     UInt,
     Int,
     Float,
     Bool,
+    /// Queried as seconds since the Unix epoch.
+    Timestamp,
+    /// Queried as seconds.
+    Duration,
+    /// Queried as a string.
+    IpAddr,
+    /// Queried as a string.
+    SocketAddr,
+    /// Number of bytes, queried as an unsigned integer.
+    ByteSize,
+    Bytes,
+    List,
 }
 
 #[derive(Debug, Clone)]
@@ -36,6 +142,31 @@ pub enum LiteralValue {
     Int(i64),
     Float(f64),
     Bool(bool),
+    Timestamp(SystemTime),
+    Duration(Duration),
+    IpAddr(IpAddr),
+    SocketAddr(SocketAddr),
+    ByteSize(u64),
+    Bytes(Arc<[u8]>),
+    List(Arc<[Option<LiteralValue>]>),
+}
+impl LiteralValue {
+    pub fn literal_type(&self) -> LiteralType {
+        match self {
+            LiteralValue::String(_) => LiteralType::String,
+            LiteralValue::UInt(_) => LiteralType::UInt,
+            LiteralValue::Int(_) => LiteralType::Int,
+            LiteralValue::Float(_) => LiteralType::Float,
+            LiteralValue::Bool(_) => LiteralType::Bool,
+            LiteralValue::Timestamp(_) => LiteralType::Timestamp,
+            LiteralValue::Duration(_) => LiteralType::Duration,
+            LiteralValue::IpAddr(_) => LiteralType::IpAddr,
+            LiteralValue::SocketAddr(_) => LiteralType::SocketAddr,
+            LiteralValue::ByteSize(_) => LiteralType::ByteSize,
+            LiteralValue::Bytes(_) => LiteralType::Bytes,
+            LiteralValue::List(_) => LiteralType::List,
+        }
+    }
 }
 impl TryFrom<LiteralValue> for String {
     type Error = ();
@@ -97,6 +228,56 @@ impl TryFrom<LiteralValue> for bool {
         Ok(v)
     }
 }
+impl TryFrom<LiteralValue> for SystemTime {
+    type Error = ();
+
+    fn try_from(value: LiteralValue) -> Result<Self, Self::Error> {
+        let LiteralValue::Timestamp(v) = value else {
+            return Err(());
+        };
+        Ok(v)
+    }
+}
+impl TryFrom<LiteralValue> for Duration {
+    type Error = ();
+
+    fn try_from(value: LiteralValue) -> Result<Self, Self::Error> {
+        let LiteralValue::Duration(v) = value else {
+            return Err(());
+        };
+        Ok(v)
+    }
+}
+impl TryFrom<LiteralValue> for IpAddr {
+    type Error = ();
+
+    fn try_from(value: LiteralValue) -> Result<Self, Self::Error> {
+        let LiteralValue::IpAddr(v) = value else {
+            return Err(());
+        };
+        Ok(v)
+    }
+}
+impl TryFrom<LiteralValue> for SocketAddr {
+    type Error = ();
+
+    fn try_from(value: LiteralValue) -> Result<Self, Self::Error> {
+        let LiteralValue::SocketAddr(v) = value else {
+            return Err(());
+        };
+        Ok(v)
+    }
+}
+impl TryFrom<LiteralValue> for Arc<[u8]> {
+    type Error = ();
+
+    fn try_from(value: LiteralValue) -> Result<Self, Self::Error> {
+        let LiteralValue::Bytes(v) = value else {
+            return Err(());
+        };
+        Ok(v)
+    }
+}
 impl From<String> for LiteralValue {
     fn from(value: String) -> Self {
         Self::String(value.into())
@@ -127,6 +308,36 @@ impl From<bool> for LiteralValue {
         Self::Bool(value)
     }
 }
+impl From<SystemTime> for LiteralValue {
+    fn from(value: SystemTime) -> Self {
+        Self::Timestamp(value)
+    }
+}
+impl From<Duration> for LiteralValue {
+    fn from(value: Duration) -> Self {
+        Self::Duration(value)
+    }
+}
+impl From<IpAddr> for LiteralValue {
+    fn from(value: IpAddr) -> Self {
+        Self::IpAddr(value)
+    }
+}
+impl From<SocketAddr> for LiteralValue {
+    fn from(value: SocketAddr) -> Self {
+        Self::SocketAddr(value)
+    }
+}
+impl From<Arc<[u8]>> for LiteralValue {
+    fn from(value: Arc<[u8]>) -> Self {
+        Self::Bytes(value)
+    }
+}
+impl From<Vec<u8>> for LiteralValue {
+    fn from(value: Vec<u8>) -> Self {
+        Self::Bytes(value.into())
+    }
+}
 impl fmt::Display for LiteralValue {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
@@ -135,6 +346,23 @@ impl fmt::Display for LiteralValue {
             LiteralValue::Int(v) => write!(f, "{v}"),
             LiteralValue::Float(v) => write!(f, "{v}"),
             LiteralValue::Bool(v) => write!(f, "{v}"),
+            LiteralValue::Timestamp(v) => write!(f, "{}", format::timestamp(*v)),
+            LiteralValue::Duration(v) => write!(f, "{}", format::duration(*v)),
+            LiteralValue::IpAddr(v) => write!(f, "{v}"),
+            LiteralValue::SocketAddr(v) => write!(f, "{v}"),
+            LiteralValue::ByteSize(v) => write!(f, "{}", format::bytes_iec(*v)),
+            LiteralValue::Bytes(v) => write!(f, "{}", format::hex(v)),
+            LiteralValue::List(v) => {
+                for (i, item) in v.iter().enumerate() {
+                    if i != 0 {
+                        write!(f, ", ")?;
+                    }
+                    if let Some(item) = item {
+                        write!(f, "{item}")?;
+                    }
+                }
+                Ok(())
+            }
         }
     }
 }
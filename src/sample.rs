@@ -0,0 +1,156 @@
+use core::fmt;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::ensure;
+
+/// Prefix of the titles of row counts scaled up from a sample, and of the footer of a sampled view.
+pub const APPROXIMATE_MARK: &str = "≈";
+
+/// Run a query over a random subset of the rows instead of all of them.
+///
+/// [`crate::table_view::en::TableViewWrite::sample`] tells how many rows were sampled, and `write_to` ends the view with a footer telling it.
+/// Row counts of a `group` statement, i.e. its `count` and `len` aggregates, are scaled by the ratio of all rows to sampled rows and marked with [`APPROXIMATE_MARK`]; other aggregates, e.g. sums, are left as computed on the sample.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Sampling {
+    pub method: SampleMethod,
+    /// Seed of the random choice; `None` picks a different sample on every query.
+    pub seed: Option<u64>,
+}
+impl Sampling {
+    pub fn uniform(fraction: f64) -> Self {
+        Self {
+            method: SampleMethod::Uniform { fraction },
+            seed: None,
+        }
+    }
+
+    pub fn reservoir(rows: usize) -> Self {
+        Self {
+            method: SampleMethod::Reservoir { rows },
+            seed: None,
+        }
+    }
+
+    /// Fail unless the sample can hold rows, i.e. the fraction is in `(0, 1]` or the reservoir is not empty.
+    pub fn check(&self) -> anyhow::Result<()> {
+        match self.method {
+            SampleMethod::Uniform { fraction } => ensure!(
+                0. < fraction && fraction <= 1.,
+                "The sampled fraction {fraction} is not in (0, 1]"
+            ),
+            SampleMethod::Reservoir { rows } => {
+                ensure!(0 < rows, "The reservoir must hold at least one row")
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum SampleMethod {
+    /// Keep each row with probability `fraction`.
+    Uniform { fraction: f64 },
+    /// Keep exactly `rows` rows, or all of them if there are fewer.
+    Reservoir { rows: usize },
+}
+
+/// How many rows a sampled view was computed from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SampleSize {
+    pub sampled: usize,
+    pub total: usize,
+}
+impl fmt::Display for SampleSize {
+    /// Render the footer of a sampled view, e.g. `≈ sampled 3 of 100 rows`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Self { sampled, total } = self;
+        write!(f, "{APPROXIMATE_MARK} sampled {sampled} of {total} rows")
+    }
+}
+
+/// Pick the keys of the sampled rows in their original order.
+pub(crate) fn sample<K: Copy + Ord>(keys: impl Iterator<Item = K>, sampling: &Sampling) -> Vec<K> {
+    let seed = sampling.seed.unwrap_or_else(|| {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_nanos() as u64)
+            .unwrap_or_default()
+    });
+    let mut rng = SplitMix64(seed);
+    let mut sampled = match sampling.method {
+        SampleMethod::Uniform { fraction } => keys.filter(|_| rng.next_f64() < fraction).collect(),
+        SampleMethod::Reservoir { rows } => {
+            // The reservoir may be far larger than the table
+            let mut reservoir = Vec::with_capacity(rows.min(keys.size_hint().0));
+            for (i, key) in keys.enumerate() {
+                if i < rows {
+                    reservoir.push(key);
+                    continue;
+                }
+                let j = rng.below(i as u64 + 1) as usize;
+                if j < rows {
+                    reservoir[j] = key;
+                }
+            }
+            reservoir
+        }
+    };
+    sampled.sort_unstable();
+    sampled
+}
+
+/// Ref: <https://prng.di.unimi.it/splitmix64.c>
+struct SplitMix64(u64);
+impl SplitMix64 {
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    }
+
+    /// Return a number in `[0, 1)`.
+    fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1_u64 << 53) as f64
+    }
+
+    /// Return a number in `[0, n)`.
+    fn below(&mut self, n: u64) -> u64 {
+        ((u128::from(self.next_u64()) * u128::from(n)) >> 64) as u64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sample() {
+        let reservoir = Sampling {
+            seed: Some(1),
+            ..Sampling::reservoir(10)
+        };
+        let keys = sample(0..1000, &reservoir);
+        assert_eq!(keys.len(), 10);
+        assert!(keys.is_sorted());
+        assert_eq!(sample(0..1000, &reservoir), keys);
+        assert_eq!(sample(0..3, &reservoir), [0, 1, 2]);
+        assert_eq!(sample(0..3, &Sampling::reservoir(usize::MAX)), [0, 1, 2]);
+
+        let uniform = Sampling {
+            seed: Some(1),
+            ..Sampling::uniform(0.1)
+        };
+        let n = sample(0..10_000, &uniform).len();
+        assert!((800..1200).contains(&n));
+        assert!(uniform.check().is_ok());
+        assert!(Sampling::uniform(0.).check().is_err());
+        assert!(Sampling::uniform(f64::NAN).check().is_err());
+        assert!(Sampling::uniform(1.5).check().is_err());
+        assert!(Sampling::reservoir(0).check().is_err());
+    }
+}
@@ -0,0 +1,612 @@
+use core::{
+    any::TypeId,
+    fmt,
+    ops::{Deref, DerefMut},
+};
+use std::{
+    collections::HashMap,
+    sync::{Arc, LazyLock, RwLock},
+};
+
+use anyhow::{Context, bail};
+use serde::{
+    Serialize,
+    ser::{self, Impossible},
+};
+
+use crate::{
+    format::Format,
+    row::{FieldVisitor, LiteralType, LiteralValue, TableRow, ValueDisplay},
+};
+
+/// A [`Serialize`] type that can be exposed as a table row through [`SerdeRow`].
+pub trait SerdeTableRow: Serialize {
+    /// Return a value whose serialization reveals the type of every column.
+    ///
+    /// Columns whose values are `None` in the sample are typed as strings, and nested structs that are `None` in the sample become a single string column.
+    fn sample() -> Self;
+}
+
+/// Expose a [`Serialize`] struct as a table row.
+///
+/// Fields of nested structs become columns named `outer.inner`; sequences and tuples become lists.
+/// Every row has the columns of [`SerdeTableRow::sample`]: fields missing from a row, e.g. of a `None` struct or of another enum variant, are null, and so are values of another type than the column.
+///
+/// Maps and structs inside sequences are not supported; queries over such rows fail.
+#[derive(Debug, Clone, Default)]
+pub struct SerdeRow<T>(pub T);
+impl<T: SerdeTableRow + 'static> SerdeRow<T> {
+    fn try_fields(&self) -> anyhow::Result<Vec<Option<LiteralValue>>> {
+        let template = template::<T>()?;
+        let node = serialize(&self.0)?;
+        let mut values = vec![];
+        fill(&template, Some(&node), &mut values);
+        Ok(values)
+    }
+}
+impl<T: SerdeTableRow + 'static> TableRow for SerdeRow<T> {
+    /// Return no columns if the sample cannot be serialized.
+    fn schema() -> Vec<(String, LiteralType)> {
+        let Ok(template) = template::<T>() else {
+            return vec![];
+        };
+        let mut schema = vec![];
+        flatten_schema(&template, "", &mut schema);
+        schema
+    }
+
+    /// Return nulls if the row cannot be serialized; queries report the error instead.
+    fn fields(&self) -> Vec<Option<LiteralValue>> {
+        self.try_fields()
+            .unwrap_or_else(|_| vec![None; Self::schema().len()])
+    }
+
+    fn try_visit_fields(&self, visitor: &mut dyn FieldVisitor) -> anyhow::Result<()> {
+        for (i, value) in self.try_fields()?.into_iter().enumerate() {
+            visitor.visit(i, value);
+        }
+        Ok(())
+    }
+}
+impl<T: ValueDisplay> ValueDisplay for SerdeRow<T> {
+    fn display_value(header: &str, value: Option<LiteralValue>) -> String {
+        T::display_value(header, value)
+    }
+    fn column_format(header: &str) -> Option<Format> {
+        T::column_format(header)
+    }
+    fn custom_display(header: &str) -> bool {
+        T::custom_display(header)
+    }
+}
+impl<T> Deref for SerdeRow<T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+impl<T> DerefMut for SerdeRow<T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
+
+/// Serialized samples of the row types, so that each sample is serialized once.
+static TEMPLATES: LazyLock<RwLock<HashMap<TypeId, Arc<Node>>>> = LazyLock::new(Default::default);
+
+/// Return the serialized [`SerdeTableRow::sample`] of `T`.
+fn template<T: SerdeTableRow + 'static>() -> anyhow::Result<Arc<Node>> {
+    let id = TypeId::of::<T>();
+    if let Some(template) = TEMPLATES.read().unwrap().get(&id) {
+        return Ok(template.clone());
+    }
+    let template = Arc::new(serialize(&T::sample())?);
+    let mut templates = TEMPLATES.write().unwrap();
+    Ok(templates.entry(id).or_insert(template).clone())
+}
+
+/// A serialized value before it is flattened into columns.
+#[derive(Debug)]
+enum Node {
+    Value(Option<LiteralValue>),
+    Struct(Vec<(&'static str, Node)>),
+}
+
+fn serialize<T: Serialize>(value: &T) -> anyhow::Result<Node> {
+    let node = value
+        .serialize(NodeSerializer)
+        .context("Failed to serialize the row")?;
+    if !matches!(node, Node::Struct(_)) {
+        bail!("Only structs can be serialized into rows");
+    }
+    Ok(node)
+}
+
+fn flatten_schema(node: &Node, name: &str, schema: &mut Vec<(String, LiteralType)>) {
+    match node {
+        Node::Value(value) => {
+            let ty = value
+                .as_ref()
+                .map(|v| v.literal_type())
+                .unwrap_or(LiteralType::String);
+            schema.push((name.to_string(), ty));
+        }
+        Node::Struct(fields) => {
+            for (key, node) in fields {
+                let name = match name.is_empty() {
+                    true => key.to_string(),
+                    false => format!("{name}.{key}"),
+                };
+                flatten_schema(node, &name, schema);
+            }
+        }
+    }
+}
+
+/// Push the values of `node` in the shape of `template`.
+fn fill(template: &Node, node: Option<&Node>, values: &mut Vec<Option<LiteralValue>>) {
+    match template {
+        Node::Value(sample) => {
+            let ty = sample
+                .as_ref()
+                .map(|v| v.literal_type())
+                .unwrap_or(LiteralType::String);
+            let value = match node {
+                Some(Node::Value(Some(value))) if value.literal_type() == ty => Some(value.clone()),
+                // Columns typed from a `None` sample show any value as text
+                Some(Node::Value(Some(value))) if ty == LiteralType::String => {
+                    Some(value.to_string().into())
+                }
+                _ => None,
+            };
+            values.push(value);
+        }
+        Node::Struct(fields) => {
+            for (key, template) in fields {
+                let node = match node {
+                    Some(Node::Struct(node)) => node.iter().find(|(k, _)| k == key).map(|(_, n)| n),
+                    _ => None,
+                };
+                fill(template, node, values);
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Error(String);
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+impl std::error::Error for Error {}
+impl ser::Error for Error {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        Self(msg.to_string())
+    }
+}
+
+fn unsupported(what: &str) -> Error {
+    Error(format!("{what} is not supported in rows"))
+}
+
+/// Serialize a value into a [`Node`].
+struct NodeSerializer;
+impl ser::Serializer for NodeSerializer {
+    type Ok = Node;
+    type Error = Error;
+    type SerializeSeq = ListSerializer;
+    type SerializeTuple = ListSerializer;
+    type SerializeTupleStruct = ListSerializer;
+    type SerializeTupleVariant = ListSerializer;
+    type SerializeMap = Impossible<Node, Error>;
+    type SerializeStruct = StructSerializer;
+    type SerializeStructVariant = StructSerializer;
+
+    fn serialize_bool(self, v: bool) -> Result<Self::Ok, Self::Error> {
+        Ok(Node::Value(Some(v.into())))
+    }
+    fn serialize_i8(self, v: i8) -> Result<Self::Ok, Self::Error> {
+        self.serialize_i64(v.into())
+    }
+    fn serialize_i16(self, v: i16) -> Result<Self::Ok, Self::Error> {
+        self.serialize_i64(v.into())
+    }
+    fn serialize_i32(self, v: i32) -> Result<Self::Ok, Self::Error> {
+        self.serialize_i64(v.into())
+    }
+    fn serialize_i64(self, v: i64) -> Result<Self::Ok, Self::Error> {
+        Ok(Node::Value(Some(v.into())))
+    }
+    fn serialize_u8(self, v: u8) -> Result<Self::Ok, Self::Error> {
+        self.serialize_u64(v.into())
+    }
+    fn serialize_u16(self, v: u16) -> Result<Self::Ok, Self::Error> {
+        self.serialize_u64(v.into())
+    }
+    fn serialize_u32(self, v: u32) -> Result<Self::Ok, Self::Error> {
+        self.serialize_u64(v.into())
+    }
+    fn serialize_u64(self, v: u64) -> Result<Self::Ok, Self::Error> {
+        Ok(Node::Value(Some(v.into())))
+    }
+    fn serialize_f32(self, v: f32) -> Result<Self::Ok, Self::Error> {
+        self.serialize_f64(v.into())
+    }
+    fn serialize_f64(self, v: f64) -> Result<Self::Ok, Self::Error> {
+        Ok(Node::Value(Some(v.into())))
+    }
+    fn serialize_char(self, v: char) -> Result<Self::Ok, Self::Error> {
+        Ok(Node::Value(Some(v.to_string().into())))
+    }
+    fn serialize_str(self, v: &str) -> Result<Self::Ok, Self::Error> {
+        Ok(Node::Value(Some(v.to_string().into())))
+    }
+    fn serialize_bytes(self, v: &[u8]) -> Result<Self::Ok, Self::Error> {
+        Ok(Node::Value(Some(v.to_vec().into())))
+    }
+    fn serialize_none(self) -> Result<Self::Ok, Self::Error> {
+        Ok(Node::Value(None))
+    }
+    fn serialize_some<T: ?Sized + Serialize>(self, value: &T) -> Result<Self::Ok, Self::Error> {
+        value.serialize(self)
+    }
+    fn serialize_unit(self) -> Result<Self::Ok, Self::Error> {
+        Ok(Node::Value(None))
+    }
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<Self::Ok, Self::Error> {
+        Ok(Node::Value(None))
+    }
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+    ) -> Result<Self::Ok, Self::Error> {
+        self.serialize_str(variant)
+    }
+    fn serialize_newtype_struct<T: ?Sized + Serialize>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<Self::Ok, Self::Error> {
+        value.serialize(self)
+    }
+    fn serialize_newtype_variant<T: ?Sized + Serialize>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        value: &T,
+    ) -> Result<Self::Ok, Self::Error> {
+        value.serialize(self)
+    }
+    fn serialize_seq(self, len: Option<usize>) -> Result<Self::SerializeSeq, Self::Error> {
+        Ok(ListSerializer {
+            items: Vec::with_capacity(len.unwrap_or(0)),
+        })
+    }
+    fn serialize_tuple(self, len: usize) -> Result<Self::SerializeTuple, Self::Error> {
+        self.serialize_seq(Some(len))
+    }
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeTupleStruct, Self::Error> {
+        self.serialize_seq(Some(len))
+    }
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeTupleVariant, Self::Error> {
+        self.serialize_seq(Some(len))
+    }
+    fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap, Self::Error> {
+        Err(unsupported("Map"))
+    }
+    fn serialize_struct(
+        self,
+        _name: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeStruct, Self::Error> {
+        Ok(StructSerializer {
+            fields: Vec::with_capacity(len),
+        })
+    }
+    fn serialize_struct_variant(
+        self,
+        name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeStructVariant, Self::Error> {
+        self.serialize_struct(name, len)
+    }
+}
+
+/// Collect the fields of a struct or of a struct variant.
+struct StructSerializer {
+    fields: Vec<(&'static str, Node)>,
+}
+impl StructSerializer {
+    fn push<T: ?Sized + Serialize>(&mut self, key: &'static str, value: &T) -> Result<(), Error> {
+        self.fields.push((key, value.serialize(NodeSerializer)?));
+        Ok(())
+    }
+}
+impl ser::SerializeStruct for StructSerializer {
+    type Ok = Node;
+    type Error = Error;
+
+    fn serialize_field<T: ?Sized + Serialize>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), Self::Error> {
+        self.push(key, value)
+    }
+    fn end(self) -> Result<Self::Ok, Self::Error> {
+        Ok(Node::Struct(self.fields))
+    }
+}
+impl ser::SerializeStructVariant for StructSerializer {
+    type Ok = Node;
+    type Error = Error;
+
+    fn serialize_field<T: ?Sized + Serialize>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), Self::Error> {
+        self.push(key, value)
+    }
+    fn end(self) -> Result<Self::Ok, Self::Error> {
+        Ok(Node::Struct(self.fields))
+    }
+}
+
+/// Collect the elements into a single list value.
+struct ListSerializer {
+    items: Vec<Option<LiteralValue>>,
+}
+impl ListSerializer {
+    fn push<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), Error> {
+        match value.serialize(NodeSerializer)? {
+            Node::Value(item) => {
+                self.items.push(item);
+                Ok(())
+            }
+            Node::Struct(_) => Err(unsupported("Struct in a list")),
+        }
+    }
+    fn finish(self) -> Result<Node, Error> {
+        Ok(Node::Value(Some(LiteralValue::List(self.items.into()))))
+    }
+}
+impl ser::SerializeSeq for ListSerializer {
+    type Ok = Node;
+    type Error = Error;
+
+    fn serialize_element<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), Self::Error> {
+        self.push(value)
+    }
+    fn end(self) -> Result<Self::Ok, Self::Error> {
+        self.finish()
+    }
+}
+impl ser::SerializeTuple for ListSerializer {
+    type Ok = Node;
+    type Error = Error;
+
+    fn serialize_element<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), Self::Error> {
+        self.push(value)
+    }
+    fn end(self) -> Result<Self::Ok, Self::Error> {
+        self.finish()
+    }
+}
+impl ser::SerializeTupleStruct for ListSerializer {
+    type Ok = Node;
+    type Error = Error;
+
+    fn serialize_field<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), Self::Error> {
+        self.push(value)
+    }
+    fn end(self) -> Result<Self::Ok, Self::Error> {
+        self.finish()
+    }
+}
+impl ser::SerializeTupleVariant for ListSerializer {
+    type Ok = Node;
+    type Error = Error;
+
+    fn serialize_field<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), Self::Error> {
+        self.push(value)
+    }
+    fn end(self) -> Result<Self::Ok, Self::Error> {
+        self.finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Serialize;
+
+    use super::*;
+    use crate::table::Table;
+
+    #[derive(Serialize)]
+    struct Addr {
+        host: String,
+        port: u16,
+    }
+    #[derive(Serialize)]
+    struct Conn {
+        id: u32,
+        peer: Addr,
+        rtt: Option<f32>,
+        tags: Vec<String>,
+    }
+    impl SerdeTableRow for Conn {
+        fn sample() -> Self {
+            Self {
+                id: 0,
+                peer: Addr {
+                    host: String::new(),
+                    port: 0,
+                },
+                rtt: Some(0.),
+                tags: vec![],
+            }
+        }
+    }
+    impl ValueDisplay for Conn {}
+
+    #[test]
+    fn test_schema() {
+        assert_eq!(
+            SerdeRow::<Conn>::schema(),
+            [
+                ("id".to_string(), LiteralType::UInt),
+                ("peer.host".to_string(), LiteralType::String),
+                ("peer.port".to_string(), LiteralType::UInt),
+                ("rtt".to_string(), LiteralType::Float),
+                ("tags".to_string(), LiteralType::List),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_to_view() {
+        let table = Table::new();
+        let _a = table.set_scope(SerdeRow(Conn {
+            id: 1,
+            peer: Addr {
+                host: "localhost".to_string(),
+                port: 80,
+            },
+            rtt: None,
+            tags: vec!["a".to_string(), "b".to_string()],
+        }));
+        let view = table.to_view("").unwrap();
+        assert_eq!(
+            view.to_string(),
+            "id peer.host peer.port rtt tags 
+ 1 localhost        80     a, b 
+"
+        );
+    }
+
+    #[test]
+    fn test_sample_serialized_once() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        static SAMPLES: AtomicUsize = AtomicUsize::new(0);
+        #[derive(Serialize)]
+        struct Point {
+            x: i64,
+        }
+        impl SerdeTableRow for Point {
+            fn sample() -> Self {
+                SAMPLES.fetch_add(1, Ordering::Relaxed);
+                Self { x: 0 }
+            }
+        }
+        impl ValueDisplay for Point {}
+
+        let table = Table::new();
+        let _rows: Vec<_> = (0..3)
+            .map(|x| table.set_scope(SerdeRow(Point { x })))
+            .collect();
+        table.to_view("").unwrap();
+        table.to_view("filter x = 1").unwrap();
+        assert_eq!(SAMPLES.load(Ordering::Relaxed), 1);
+    }
+
+    #[derive(Serialize)]
+    enum State {
+        Idle,
+        Busy(u32),
+        Failed { code: i64 },
+    }
+    #[derive(Serialize)]
+    struct Job {
+        id: u32,
+        peer: Option<Addr>,
+        state: State,
+    }
+    impl SerdeTableRow for Job {
+        fn sample() -> Self {
+            Self {
+                id: 0,
+                peer: Some(Addr {
+                    host: String::new(),
+                    port: 0,
+                }),
+                state: State::Busy(0),
+            }
+        }
+    }
+    impl ValueDisplay for Job {}
+
+    #[test]
+    fn test_varying_shapes() {
+        let table = Table::new();
+        let _a = table.set_scope(SerdeRow(Job {
+            id: 1,
+            peer: None,
+            state: State::Idle,
+        }));
+        let _b = table.set_scope(SerdeRow(Job {
+            id: 2,
+            peer: Some(Addr {
+                host: "localhost".to_string(),
+                port: 80,
+            }),
+            state: State::Busy(3),
+        }));
+        let _c = table.set_scope(SerdeRow(Job {
+            id: 3,
+            peer: None,
+            state: State::Failed { code: -1 },
+        }));
+        let view = table.to_view("sort id").unwrap();
+        assert_eq!(
+            view.to_string(),
+            "id peer.host peer.port state 
+ 1                           
+ 2 localhost        80     3 
+ 3                           
+"
+        );
+    }
+
+    #[derive(Serialize)]
+    struct Labels {
+        labels: std::collections::BTreeMap<String, String>,
+    }
+    impl SerdeTableRow for Labels {
+        fn sample() -> Self {
+            Self {
+                labels: Default::default(),
+            }
+        }
+    }
+    impl ValueDisplay for Labels {}
+
+    #[test]
+    fn test_unsupported() {
+        let table = Table::new();
+        let _a = table.set_scope(SerdeRow(Labels {
+            labels: [("a".to_string(), "b".to_string())].into(),
+        }));
+        let e = table.to_view("").unwrap_err();
+        assert!(format!("{e:#}").contains("Map is not supported in rows"));
+    }
+}
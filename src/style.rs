@@ -0,0 +1,280 @@
+use core::fmt;
+use std::sync::Arc;
+
+use crate::row::LiteralValue;
+
+/// One of the 16 standard terminal colors or an index of the 256-color palette.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum Color {
+    Black,
+    Red,
+    Green,
+    Yellow,
+    Blue,
+    Magenta,
+    Cyan,
+    White,
+    Gray,
+    Fixed(u8),
+}
+impl Color {
+    fn sgr(self, background: bool) -> String {
+        let base = if background { 40 } else { 30 };
+        let code = match self {
+            Color::Black => base,
+            Color::Red => base + 1,
+            Color::Green => base + 2,
+            Color::Yellow => base + 3,
+            Color::Blue => base + 4,
+            Color::Magenta => base + 5,
+            Color::Cyan => base + 6,
+            Color::White => base + 7,
+            Color::Gray => base + 60,
+            Color::Fixed(n) => return format!("{};5;{n}", base + 8),
+        };
+        code.to_string()
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct Style {
+    pub fg: Option<Color>,
+    pub bg: Option<Color>,
+    pub bold: bool,
+    pub dim: bool,
+    pub underline: bool,
+}
+impl Style {
+    pub fn fg(color: Color) -> Self {
+        Self {
+            fg: Some(color),
+            ..Default::default()
+        }
+    }
+
+    pub fn dim() -> Self {
+        Self {
+            dim: true,
+            ..Default::default()
+        }
+    }
+
+    /// Return `self` with the colors and attributes set in `other` on top.
+    pub fn merge(self, other: Self) -> Self {
+        Self {
+            fg: other.fg.or(self.fg),
+            bg: other.bg.or(self.bg),
+            bold: self.bold || other.bold,
+            dim: self.dim || other.dim,
+            underline: self.underline || other.underline,
+        }
+    }
+
+    pub fn is_plain(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Return the escape sequence that turns the style on.
+    pub(crate) fn escape(&self) -> String {
+        let mut codes = vec![];
+        if self.bold {
+            codes.push("1".to_string());
+        }
+        if self.dim {
+            codes.push("2".to_string());
+        }
+        if self.underline {
+            codes.push("4".to_string());
+        }
+        if let Some(fg) = self.fg {
+            codes.push(fg.sgr(false));
+        }
+        if let Some(bg) = self.bg {
+            codes.push(bg.sgr(true));
+        }
+        format!("\x1b[{}m", codes.join(";"))
+    }
+}
+
+/// Escape sequence that turns every style off.
+pub(crate) const RESET: &str = "\x1b[0m";
+
+pub type Predicate = Arc<dyn Fn(&LiteralValue) -> bool + Send + Sync>;
+
+/// Style cells whose value in `column` matches the predicate.
+///
+/// Null values never match.
+#[derive(Clone)]
+pub struct StyleRule {
+    pub column: String,
+    pub predicate: Predicate,
+    pub style: Style,
+    /// Style every cell of the matching row instead of the cell in `column` only.
+    pub whole_row: bool,
+}
+impl StyleRule {
+    pub fn cell(
+        column: String,
+        predicate: impl Fn(&LiteralValue) -> bool + Send + Sync + 'static,
+        style: Style,
+    ) -> Self {
+        Self {
+            column,
+            predicate: Arc::new(predicate),
+            style,
+            whole_row: false,
+        }
+    }
+
+    pub fn row(
+        column: String,
+        predicate: impl Fn(&LiteralValue) -> bool + Send + Sync + 'static,
+        style: Style,
+    ) -> Self {
+        Self {
+            whole_row: true,
+            ..Self::cell(column, predicate, style)
+        }
+    }
+}
+impl fmt::Debug for StyleRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("StyleRule")
+            .field("column", &self.column)
+            .field("style", &self.style)
+            .field("whole_row", &self.whole_row)
+            .finish_non_exhaustive()
+    }
+}
+impl PartialEq for StyleRule {
+    fn eq(&self, other: &Self) -> bool {
+        self.column == other.column
+            && Arc::ptr_eq(&self.predicate, &other.predicate)
+            && self.style == other.style
+            && self.whole_row == other.whole_row
+    }
+}
+
+/// Whether to emit ANSI escapes, decided when the view is written.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum ColorChoice {
+    /// Only if the destination is a terminal and `NO_COLOR` is not set.
+    ///
+    /// [`fmt::Display`] cannot tell its destination and renders plain text; see [`crate::table_view::en::TableViewWrite::write_to`].
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+impl ColorChoice {
+    /// Whether to emit ANSI escapes to a destination that is a terminal or not.
+    pub fn enabled(self, terminal: bool) -> bool {
+        match self {
+            ColorChoice::Auto => terminal && std::env::var_os("NO_COLOR").is_none(),
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+        }
+    }
+}
+
+/// Return the style of each cell of a row whose values are in the order of `headers`.
+///
+/// Row rules are applied before cell rules, and later rules win over earlier ones.
+pub(crate) fn row_styles(
+    rules: &[StyleRule],
+    headers: &[String],
+    row: &[Option<LiteralValue>],
+) -> Vec<Style> {
+    let mut styles = vec![Style::default(); headers.len()];
+    let matches = |rule: &StyleRule| {
+        let i = headers.iter().position(|h| *h == rule.column)?;
+        let value = row[i].as_ref()?;
+        (rule.predicate)(value).then_some(i)
+    };
+    for rule in rules.iter().filter(|r| r.whole_row) {
+        if matches(rule).is_some() {
+            for style in &mut styles {
+                *style = style.merge(rule.style);
+            }
+        }
+    }
+    for rule in rules.iter().filter(|r| !r.whole_row) {
+        if let Some(i) = matches(rule) {
+            styles[i] = styles[i].merge(rule.style);
+        }
+    }
+    styles
+}
+
+/// Count the characters of `s` that take up space on a terminal, skipping ANSI escapes.
+pub(crate) fn visible_len(s: &str) -> usize {
+    let mut len = 0;
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c != '\x1b' {
+            len += 1;
+            continue;
+        }
+        // Skip a CSI sequence up to its final byte
+        if chars.next() == Some('[') {
+            for c in chars.by_ref() {
+                if ('@'..='~').contains(&c) {
+                    break;
+                }
+            }
+        }
+    }
+    len
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_row_styles() {
+        let rules = [
+            StyleRule::row(
+                "idle".to_string(),
+                |v| matches!(v, LiteralValue::Bool(true)),
+                Style::dim(),
+            ),
+            StyleRule::cell(
+                "cpu".to_string(),
+                |v| matches!(v, LiteralValue::Float(x) if 90. < *x),
+                Style::fg(Color::Red),
+            ),
+        ];
+        let headers = ["cpu".to_string(), "idle".to_string()];
+        let styles = row_styles(&rules, &headers, &[Some(95.0.into()), Some(true.into())]);
+        assert_eq!(
+            styles,
+            [
+                Style {
+                    fg: Some(Color::Red),
+                    dim: true,
+                    ..Default::default()
+                },
+                Style::dim(),
+            ]
+        );
+        let styles = row_styles(&rules, &headers, &[None, Some(false.into())]);
+        assert!(styles.iter().all(|s| s.is_plain()));
+        assert_eq!(
+            styles[0].merge(Style::fg(Color::Fixed(208))).escape(),
+            "\x1b[38;5;208m"
+        );
+    }
+
+    #[test]
+    fn test_visible_len() {
+        assert_eq!(visible_len("\x1b[1;31mhé\x1b[0m"), 2);
+        assert_eq!(visible_len("abc"), 3);
+    }
+}
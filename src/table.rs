@@ -1,131 +1,432 @@
-use std::sync::{Arc, RwLock};
+use core::fmt;
+use std::{
+    collections::BTreeMap,
+    net::{IpAddr, SocketAddr},
+    sync::{
+        Arc, Mutex, RwLock,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
 
-use anyhow::Context;
+use anyhow::{Context, bail, ensure};
 use dfsql::backend::{
     Frame,
     dynamic::{Column, Value},
 };
 use primitive::iter::vec_zip::VecZip;
-use slotmap::{SlotMap, new_key_type};
+use slotmap::{KeyData, SlotMap, new_key_type};
 
 use crate::{
-    row::{LiteralType, LiteralValue, TableRow, ValueDisplay},
+    cache::{self, QueryCache, Statement},
+    capacity::{Capacity, CapacityError, CapacityPolicy, Limits, TableStats},
+    complete::{self, Completion},
+    diff::Snapshot,
+    format,
+    options::{MetaColumn, NamedView, ViewOptions},
+    plan::QueryPlan,
+    row::{FieldVisitor, LiteralType, LiteralValue, TableRow, ValueDisplay},
+    sample::{self, APPROXIMATE_MARK, SampleSize},
+    style::{self, ColorChoice},
     table_view::{
         TableView,
         en::{Alignment, TableViewWrite},
     },
+    validate,
 };
 
 #[derive(Debug)]
 pub struct Table<R> {
-    rows: Arc<RwLock<SlotMap<RowKey, R>>>,
+    rows: Arc<RwLock<SlotMap<RowKey, Slot<R>>>>,
+    /// Bumped on every change to the rows while holding the write lock of `rows`.
+    generation: Arc<AtomicU64>,
+    cache: Arc<Mutex<QueryCache>>,
+    views: Arc<RwLock<BTreeMap<String, NamedView>>>,
+    default_view: Arc<RwLock<NamedView>>,
+    /// Locked while holding the write lock of `rows`.
+    limits: Arc<Mutex<Limits>>,
 }
 impl<R: TableRow + ValueDisplay> Table<R> {
+    /// Run the query with the options of the default view, or run the default query if `sql` is empty.
     pub fn to_view(&self, sql: &str) -> anyhow::Result<TableViewWrite> {
-        let sql = dfsql::sql::parse(sql)?;
+        let default = self.default_view();
+        let sql = if sql.trim().is_empty() {
+            &default.sql
+        } else {
+            sql
+        };
+        self.to_view_with(sql, &default.options)
+    }
+
+    pub fn to_view_with(&self, sql: &str, options: &ViewOptions) -> anyhow::Result<TableViewWrite> {
+        self.query(&R::schema(), &StaticAccess, None, sql, options)
+    }
+
+    /// Keep the values of a query result to compare with a later one by [`crate::diff::diff`].
+    pub fn snapshot(&self, sql: &str, options: &ViewOptions) -> anyhow::Result<Snapshot> {
+        self.query_snapshot(&R::schema(), &StaticAccess, sql, options)
+    }
 
+    /// Render the view registered by [`Table::register_view`].
+    pub fn to_named_view(&self, name: &str) -> anyhow::Result<TableViewWrite> {
+        let view = self.named_view(name)?;
+        self.to_view_with(&view.sql, &view.options)
+    }
+
+    /// Suggest statements, keywords and columns of [`TableRow::schema`] for the partial word ending at byte `cursor` of `sql`.
+    pub fn complete(&self, sql: &str, cursor: usize) -> Vec<Completion> {
+        complete::complete(sql, cursor, &R::schema())
+    }
+
+    /// Check the query against [`TableRow::schema`] without reading any rows.
+    ///
+    /// Errors caused by unknown columns carry a [`crate::validate::ValidationError`] as their context.
+    /// Meta columns are unknown; check queries that use them with [`Table::prepare_with`].
+    pub fn validate(&self, sql: &str) -> anyhow::Result<()> {
         let schema = R::schema();
+        cache::parse(sql)
+            .and_then(|statement| check_statement(&schema, &statement))
+            .map_err(|e| validate::explain(e, sql, &schema))
+    }
 
-        let mut columns: Vec<Vec<Option<LiteralValue>>> =
-            std::iter::repeat_n(vec![], schema.len()).collect();
-        {
-            let rows = self.rows.read().unwrap();
-            for (_k, r) in rows.iter() {
-                for (i, cell) in r.fields().into_iter().enumerate() {
-                    columns[i].push(cell);
-                }
+    /// Parse the query once and check it against [`TableRow::schema`] so that it can be run on every refresh without parsing it again.
+    pub fn prepare(&self, sql: &str) -> anyhow::Result<PreparedQuery<R>> {
+        self.prepare_with(sql, &[])
+    }
+
+    /// Like [`Table::prepare`] for a query that also reads the `meta_columns`.
+    pub fn prepare_with(
+        &self,
+        sql: &str,
+        meta_columns: &[MetaColumn],
+    ) -> anyhow::Result<PreparedQuery<R>> {
+        let meta_columns = ViewOptions {
+            meta_columns: meta_columns.to_vec(),
+            ..Default::default()
+        }
+        .meta_columns();
+        let schema = with_meta_columns(&R::schema(), &meta_columns)?;
+        let statement = cache::parse(sql).map_err(|e| validate::explain(e, sql, &schema))?;
+        check_statement(&schema, &statement).map_err(|e| validate::explain(e, sql, &schema))?;
+        let plan = QueryPlan::new(sql, &schema);
+        Ok(PreparedQuery {
+            table: self.clone(),
+            sql: sql.to_string(),
+            schema,
+            meta_columns,
+            plan,
+            statement,
+        })
+    }
+}
+impl<R: ValueDisplay> Table<R> {
+    /// Run the query over the rows as read by `access` under `schema`.
+    ///
+    /// `schema_generation` is the generation read before `schema` was; the view is only cached if the rows are still at it, so that a view of an outdated schema is never cached under a newer generation.
+    pub(crate) fn query(
+        &self,
+        schema: &[(String, LiteralType)],
+        access: &impl RowAccess<R>,
+        schema_generation: Option<u64>,
+        sql: &str,
+        options: &ViewOptions,
+    ) -> anyhow::Result<TableViewWrite> {
+        let schema = &with_meta_columns(schema, &options.meta_columns())?;
+        let statement = {
+            let mut cache = self.cache.lock().unwrap();
+            if let Some(view) = cache.view(sql, self.generation(), options) {
+                return Ok(view);
             }
+            cache
+                .statement(sql)
+                .map_err(|e| validate::explain(e, sql, schema))?
+        };
+        let plan = QueryPlan::new(sql, schema);
+        let (generation, view) = self
+            .execute(schema, access, &plan, &statement, options)
+            .map_err(|e| validate::explain(e, sql, schema))?;
+        if schema_generation.is_none_or(|g| g == generation) {
+            self.cache
+                .lock()
+                .unwrap()
+                .insert_view(sql, generation, options, view.clone());
         }
+        Ok(view)
+    }
 
-        let mut dyn_columns = vec![];
-        for ((header, ty), column) in schema.iter().zip(columns) {
-            let header = header.clone();
-            let c = match ty {
-                LiteralType::String => {
-                    let v: Vec<Option<String>> = column
-                        .into_iter()
-                        .map(|cell| cell.map(|v| v.try_into().unwrap()))
-                        .collect();
-                    Column::new(header, v)
+    /// Return the view together with the generation of the rows it was built from.
+    fn execute(
+        &self,
+        schema: &[(String, LiteralType)],
+        access: &impl RowAccess<R>,
+        plan: &QueryPlan,
+        statement: &Statement,
+        options: &ViewOptions,
+    ) -> anyhow::Result<(u64, TableViewWrite)> {
+        let (generation, output) = self.fetch(schema, access, plan, statement, options)?;
+        Ok((generation, output.render::<R>(options)?))
+    }
+
+    /// Run the query over the rows as read by `access` and keep the values for [`crate::diff`].
+    pub(crate) fn query_snapshot(
+        &self,
+        schema: &[(String, LiteralType)],
+        access: &impl RowAccess<R>,
+        sql: &str,
+        options: &ViewOptions,
+    ) -> anyhow::Result<Snapshot> {
+        let schema = &with_meta_columns(schema, &options.meta_columns())?;
+        let plan = QueryPlan::new(sql, schema);
+        let (_, output) = cache::parse(sql)
+            .and_then(|statement| self.fetch(schema, access, &plan, &statement, options))
+            .map_err(|e| validate::explain(e, sql, schema))?;
+        Ok(output.snapshot::<R>(options))
+    }
+
+    /// Return the decoded values of the query result in display order together with the generation of the rows they were built from.
+    ///
+    /// `schema` ends with the meta columns of `options`.
+    fn fetch(
+        &self,
+        schema: &[(String, LiteralType)],
+        access: &impl RowAccess<R>,
+        plan: &QueryPlan,
+        statement: &Statement,
+        options: &ViewOptions,
+    ) -> anyhow::Result<(u64, QueryOutput)> {
+        let needed: Vec<usize> = match plan.columns() {
+            Some(columns) => columns.to_vec(),
+            None => (0..schema.len()).collect(),
+        };
+        let mut columns = ColumnBuilders {
+            columns: needed
+                .iter()
+                .map(|&i| ColumnBuilder::new(schema[i].1))
+                .collect(),
+        };
+        let meta_columns = &options.meta_columns();
+        let base = schema.len() - meta_columns.len();
+        // One clock reading for all rows so that their ages compare consistently
+        let now = Instant::now();
+        let meta = |key: RowKey, slot: &Slot<R>, j: usize| {
+            Some(meta_value(meta_columns[j], key, &slot.meta, now))
+        };
+        // Rows read once per query into this buffer when filters or a projection pick values out of them
+        let mut buffer = RowBuffer {
+            values: vec![None; base],
+        };
+        if let Some(sampling) = &options.sampling {
+            sampling.check()?;
+        }
+        let generation;
+        let mut sample_size = None;
+        {
+            let rows = self.rows.read().unwrap();
+            generation = self.generation();
+            let sampled = options
+                .sampling
+                .as_ref()
+                .map(|sampling| sample::sample(rows.keys(), sampling));
+            let selected: Box<dyn Iterator<Item = (RowKey, &Slot<R>)>> = match &sampled {
+                Some(keys) => {
+                    sample_size = Some(SampleSize {
+                        sampled: keys.len(),
+                        total: rows.len(),
+                    });
+                    Box::new(keys.iter().map(|&k| (k, &rows[k])))
                 }
-                LiteralType::UInt => {
-                    let v: Vec<Option<u64>> = column
-                        .into_iter()
-                        .map(|cell| cell.map(|v| v.try_into().unwrap()))
-                        .collect();
-                    Column::new(header, v)
+                None => Box::new(rows.iter()),
+            };
+            for (k, slot) in selected {
+                if plan.filters().is_empty() && plan.columns().is_none() {
+                    access.visit_fields(&slot.row, &mut columns)?;
+                    for j in 0..meta_columns.len() {
+                        columns.visit(base + j, meta(k, slot, j));
+                    }
+                    continue;
                 }
-                LiteralType::Int => {
-                    let v: Vec<Option<i64>> = column
-                        .into_iter()
-                        .map(|cell| cell.map(|v| v.try_into().unwrap()))
-                        .collect();
-                    Column::new(header, v)
+                buffer.values.fill(None);
+                access.visit_fields(&slot.row, &mut buffer)?;
+                let mut pass = true;
+                for f in plan.filters() {
+                    let value = match f.column().checked_sub(base) {
+                        None => buffer.values[f.column()].clone(),
+                        Some(j) => meta(k, slot, j),
+                    };
+                    if !f.matches(value.as_ref()) {
+                        pass = false;
+                        break;
+                    }
                 }
-                LiteralType::Float => {
-                    let v: Vec<Option<f64>> = column
-                        .into_iter()
-                        .map(|cell| cell.map(|v| v.try_into().unwrap()))
-                        .collect();
-                    Column::new(header, v)
+                if !pass {
+                    continue;
                 }
-                LiteralType::Bool => {
-                    let v: Vec<Option<bool>> = column
-                        .into_iter()
-                        .map(|cell| cell.map(|v| v.try_into().unwrap()))
-                        .collect();
-                    Column::new(header, v)
+                for (column, &i) in needed.iter().enumerate() {
+                    let value = match i.checked_sub(base) {
+                        None => buffer.values[i].take(),
+                        Some(j) => meta(k, slot, j),
+                    };
+                    columns.visit(column, value);
                 }
-            };
-            dyn_columns.push(c);
+            }
         }
 
+        let dyn_columns = needed
+            .iter()
+            .zip(columns.columns)
+            .map(|(&i, column)| column.build(schema[i].0.clone()))
+            .collect();
         let frame = Frame::new(dyn_columns)?;
         let mut executor = dfsql::backend::DynamicExecutor::from_frame("table", frame);
-        executor.execute(&sql)?;
+        statement(&mut executor)?;
 
         let frame = executor.collect()?;
         let headers = frame.column_names();
         let dyn_frame = frame.to_dynamic()?;
+        let frame_columns: Vec<_> = dyn_frame.columns().collect();
+        let order = options.column_order(&headers);
         let mut out_columns = vec![];
         let mut alignments = vec![];
-        for col in dyn_frame.columns() {
-            let values = col.values();
-            let t = infer_type(&values);
-            let column: Vec<Option<LiteralValue>> = values
-                .into_iter()
-                .map(|v| match v {
-                    Value::Null => None,
-                    Value::Bool(b) => Some(b.into()),
-                    Value::UInt(u) => Some(u.into()),
-                    Value::Int(i) => Some(i.into()),
-                    Value::Float(f) => Some(f.into()),
-                    Value::String(s) => Some(LiteralValue::String(s)),
-                    Value::Bytes(_) | Value::List(_) => None,
-                })
-                .collect();
-            out_columns.push(column.into_iter());
-            alignments.push(alignment(t));
-        }
-
-        let rows = VecZip::new(out_columns)
-            .map(|r| {
-                let r: Arc<[Arc<str>]> = r
-                    .into_iter()
-                    .enumerate()
-                    .map(|(i, c)| {
-                        let header = &headers[i];
-                        let c: Arc<str> = R::display_value(header, c).into();
+        let mut approximate = vec![];
+        for (frame_column, header) in frame_columns.iter().zip(&headers) {
+            let values = frame_column.values();
+            let source = schema.iter().find(|(h, _)| h == header).map(|(_, t)| *t);
+            let t = output_type(source, &values);
+            let mut column: Vec<Option<LiteralValue>> =
+                values.into_iter().map(|v| from_frame_value(v, t)).collect();
+            let scaled = sample_size.is_some()
+                && plan.count_columns().contains(header)
+                && plan.group_keys().is_some_and(|keys| !keys.contains(header));
+            if let Some(SampleSize { sampled, total }) = sample_size
+                && scaled
+            {
+                let scale = total as f64 / sampled.max(1) as f64;
+                column
+                    .iter_mut()
+                    .flatten()
+                    .for_each(|v| scale_count(v, scale));
+            }
+            approximate.push(scaled);
+            out_columns.push(column);
+            alignments.push(
+                options
+                    .alignments
+                    .get(header)
+                    .copied()
+                    .unwrap_or_else(|| alignment(t)),
+            );
+        }
+        let output = QueryOutput {
+            headers,
+            columns: out_columns,
+            alignments,
+            approximate,
+            order,
+            sample: sample_size,
+        };
+        Ok((generation, output))
+    }
+}
+
+/// Values of a query result after dfsql and before rendering.
+///
+/// Hidden columns are kept so that style rules can still read them.
+struct QueryOutput {
+    headers: Vec<String>,
+    columns: Vec<Vec<Option<LiteralValue>>>,
+    alignments: Vec<Alignment>,
+    /// Whether each column is a row count scaled up from a sample.
+    approximate: Vec<bool>,
+    /// Indices of the visible columns in display order.
+    order: Vec<usize>,
+    sample: Option<SampleSize>,
+}
+impl QueryOutput {
+    /// Return the values row by row; a result without visible columns has no rows to show.
+    fn rows(
+        columns: Vec<Vec<Option<LiteralValue>>>,
+        order: &[usize],
+    ) -> Box<dyn Iterator<Item = Vec<Option<LiteralValue>>>> {
+        if order.is_empty() {
+            return Box::new(std::iter::empty());
+        }
+        Box::new(VecZip::new(
+            columns.into_iter().map(|c| c.into_iter()).collect(),
+        ))
+    }
+
+    fn render<R: ValueDisplay>(self, options: &ViewOptions) -> anyhow::Result<TableViewWrite> {
+        let Self {
+            headers,
+            columns,
+            alignments,
+            approximate,
+            order,
+            sample,
+        } = self;
+        let styled = !options.style_rules.is_empty() && options.color != ColorChoice::Never;
+        let mut styles = vec![];
+        let rows = Self::rows(columns, &order)
+            .map(|mut r| {
+                if styled {
+                    let row_styles = style::row_styles(&options.style_rules, &headers, &r);
+                    styles.push(order.iter().map(|&i| row_styles[i]).collect());
+                }
+                let r: Arc<[Arc<str>]> = order
+                    .iter()
+                    .map(|&i| {
+                        let c: Arc<str> =
+                            display_value::<R>(&headers[i], r[i].take(), options).into();
                         c
                     })
                     .collect();
                 r
             })
             .collect();
-        let titles = headers.into_iter().map(|t| t.into()).collect();
+        let titles = order
+            .iter()
+            .map(|&i| match approximate[i] {
+                true => format!("{APPROXIMATE_MARK}{}", headers[i]).into(),
+                false => headers[i].as_str().into(),
+            })
+            .collect();
+        let alignments = order.iter().map(|&i| alignments[i]).collect();
 
         let t = TableView::new(titles, rows).context("Failed to build the table view")?;
-        Ok(TableViewWrite::new(t, alignments.into()).unwrap())
+        let mut view = TableViewWrite::new(t, alignments).unwrap();
+        if styled {
+            view.set_styles(styles);
+            view.set_color(options.color);
+        }
+        if let Some(sample) = sample {
+            view.set_sample(sample);
+        }
+        Ok(view)
+    }
+
+    fn snapshot<R: ValueDisplay>(self, options: &ViewOptions) -> Snapshot {
+        let Self {
+            headers,
+            columns,
+            alignments,
+            approximate: _,
+            order,
+            sample: _,
+        } = self;
+        let headers: Vec<String> = order.iter().map(|&i| headers[i].clone()).collect();
+        let alignments = order.iter().map(|&i| alignments[i]).collect();
+        let rows = Self::rows(columns, &order)
+            .map(|mut r| {
+                let values: Vec<_> = order.iter().map(|&i| r[i].take()).collect();
+                let cells = values
+                    .iter()
+                    .zip(&headers)
+                    .map(|(v, header)| display_value::<R>(header, v.clone(), options).into())
+                    .collect();
+                (values, cells)
+            })
+            .collect();
+        Snapshot::new(headers, alignments, rows)
     }
 }
 impl<R> Table<R> {
@@ -133,21 +434,197 @@ impl<R> Table<R> {
     pub fn new() -> Self {
         Self {
             rows: Arc::new(RwLock::new(SlotMap::with_key())),
+            generation: Arc::new(AtomicU64::new(0)),
+            cache: Arc::new(Mutex::new(QueryCache::default())),
+            views: Arc::new(RwLock::new(BTreeMap::new())),
+            default_view: Arc::new(RwLock::new(NamedView::default())),
+            limits: Arc::new(Mutex::new(Limits::default())),
         }
     }
 
+    /// Return a counter that changes whenever a row is inserted, removed or mutated.
+    pub fn generation(&self) -> u64 {
+        self.generation.load(Ordering::Acquire)
+    }
+
+    /// Return the new generation.
+    pub(crate) fn bump_generation(&self) -> u64 {
+        self.generation.fetch_add(1, Ordering::AcqRel) + 1
+    }
+
+    /// Save a query under `name`, replacing and returning the view previously saved under it.
+    pub fn register_view(&self, name: String, view: NamedView) -> Option<NamedView> {
+        self.views.write().unwrap().insert(name, view)
+    }
+
+    /// Save every view, e.g. the views deserialized from a config file shared by operators.
+    pub fn register_views(&self, views: impl IntoIterator<Item = (String, NamedView)>) {
+        self.views.write().unwrap().extend(views);
+    }
+
+    pub fn remove_view(&self, name: &str) -> Option<NamedView> {
+        self.views.write().unwrap().remove(name)
+    }
+
+    /// Return the saved views ordered by name.
+    pub fn views(&self) -> Vec<(String, NamedView)> {
+        let views = self.views.read().unwrap();
+        views
+            .iter()
+            .map(|(name, view)| (name.clone(), view.clone()))
+            .collect()
+    }
+
+    /// Set the query and options [`Table::to_view`] falls back to.
+    pub fn set_default_view(&self, view: NamedView) {
+        *self.default_view.write().unwrap() = view;
+    }
+
+    pub fn default_view(&self) -> NamedView {
+        self.default_view.read().unwrap().clone()
+    }
+
+    /// Apply the default query, display options and named views of a table in a config file.
+    #[cfg(feature = "config")]
+    pub fn apply_config(&self, config: &crate::config::TableConfig) {
+        self.set_default_view(NamedView {
+            sql: config.default_query.clone(),
+            options: config.options.clone(),
+        });
+        self.register_views(config.views.clone());
+    }
+
+    pub(crate) fn named_view(&self, name: &str) -> anyhow::Result<NamedView> {
+        let views = self.views.read().unwrap();
+        if let Some(view) = views.get(name) {
+            return Ok(view.clone());
+        }
+        let names: Vec<&str> = views.keys().map(|k| k.as_str()).collect();
+        let hint = match validate::suggest(name, names.iter().copied()) {
+            Some(suggestion) => format!(", did you mean `{suggestion}`?"),
+            None => ".".to_string(),
+        };
+        bail!(
+            "Unknown view `{name}`{hint} Available views: {}",
+            names.join(", ")
+        )
+    }
+
+    /// Cache parsed queries and their views, or disable the cache with `None`.
+    ///
+    /// A view is reused while the table is unchanged, and for up to `max_staleness` after the table changed.
+    /// The cache is disabled by default.
+    pub fn set_query_cache(&self, max_staleness: Option<Duration>) {
+        self.cache.lock().unwrap().set_max_staleness(max_staleness);
+    }
+
+    /// Bound the number of rows, or remove the bound with `None`.
+    ///
+    /// Rows beyond a lowered bound stay until the next insertion.
+    pub fn set_capacity(&self, capacity: Option<Capacity>) -> anyhow::Result<()> {
+        if let Some(capacity) = capacity {
+            ensure!(
+                0 < capacity.max_rows,
+                "The capacity must allow at least one row"
+            );
+        }
+        let map = self.rows.write().unwrap();
+        let mut limits = self.limits.lock().unwrap();
+        limits.capacity = capacity;
+        limits.order = match capacity {
+            Some(capacity) => map
+                .iter()
+                .map(|(k, slot)| (slot.meta.order(capacity.policy), k))
+                .collect(),
+            None => Default::default(),
+        };
+        Ok(())
+    }
+
+    pub fn stats(&self) -> TableStats {
+        let rows = self.rows.read().unwrap();
+        let limits = self.limits.lock().unwrap();
+        TableStats {
+            rows: rows.len(),
+            max_rows: limits.capacity.map(|c| c.max_rows),
+            evicted: limits.evicted,
+            rejected: limits.rejected,
+        }
+    }
+
+    /// Query [`Table::stats`] as a table of one row.
+    pub fn stats_view(&self, sql: &str) -> anyhow::Result<TableViewWrite> {
+        let table = Table::new();
+        let _stats = table.set_scope(self.stats());
+        table.to_view(sql)
+    }
+
+    /// Insert a row, evicting rows by the [`CapacityPolicy`] if the table is full.
     #[must_use]
     pub fn insert(&self, row: R) -> RowKey {
         let mut map = self.rows.write().unwrap();
-        map.insert(row)
+        let mut limits = self.limits.lock().unwrap();
+        if let Some(capacity) = limits.capacity {
+            evict(&mut map, &mut limits, capacity);
+        }
+        self.insert_locked(&mut map, &mut limits, row)
+    }
+
+    /// Insert a row, or fail with a [`CapacityError`] instead of evicting rows if the table is full.
+    ///
+    /// Use it where a flood of rows should be refused, e.g. for connections of untrusted clients.
+    pub fn try_insert(&self, row: R) -> anyhow::Result<RowKey> {
+        let mut map = self.rows.write().unwrap();
+        let mut limits = self.limits.lock().unwrap();
+        if let Some(capacity) = limits.capacity
+            && capacity.max_rows <= map.len()
+        {
+            limits.rejected += 1;
+            return Err(CapacityError {
+                max_rows: capacity.max_rows,
+            }
+            .into());
+        }
+        Ok(self.insert_locked(&mut map, &mut limits, row))
+    }
+
+    fn insert_locked(
+        &self,
+        map: &mut SlotMap<RowKey, Slot<R>>,
+        limits: &mut Limits,
+        row: R,
+    ) -> RowKey {
+        let generation = self.bump_generation();
+        let key = map.insert(Slot {
+            row,
+            meta: RowMeta::new(generation),
+        });
+        if limits.capacity.is_some() {
+            limits.order.insert(generation, key);
+        }
+        key
+    }
+
+    /// Read the row under `key`, e.g. a key parsed from the `_key` column with [`RowKey::from_id`].
+    pub fn inspect<T>(&self, key: RowKey, f: impl FnOnce(&R) -> T) -> Option<T> {
+        let map = self.rows.read().unwrap();
+        map.get(key).map(|slot| f(&slot.row))
     }
 
+    /// Insert a row that is removed when the guard drops; see [`Table::insert`].
     #[must_use]
     pub fn set_scope(&self, row: R) -> RowGuard<'_, R> {
         let key = self.insert(row);
         RowGuard { table: self, key }
     }
 
+    /// See [`Table::try_insert`].
+    pub fn try_set_scope(&self, row: R) -> anyhow::Result<RowGuard<'_, R>> {
+        let key = self.try_insert(row)?;
+        Ok(RowGuard { table: self, key })
+    }
+
+    /// Like [`Table::set_scope`] with a guard that owns a handle to the table.
     #[must_use]
     pub fn set_scope_owned(&self, row: R) -> RowOwnedGuard<R> {
         let key = self.insert(row);
@@ -157,9 +634,70 @@ impl<R> Table<R> {
         }
     }
 
+    /// See [`Table::try_insert`].
+    pub fn try_set_scope_owned(&self, row: R) -> anyhow::Result<RowOwnedGuard<R>> {
+        let key = self.try_insert(row)?;
+        Ok(RowOwnedGuard {
+            table: self.clone(),
+            key,
+        })
+    }
+
     pub fn remove(&self, key: RowKey) -> Option<R> {
         let mut map = self.rows.write().unwrap();
-        map.remove(key)
+        let slot = map.remove(key)?;
+        let mut limits = self.limits.lock().unwrap();
+        if let Some(capacity) = limits.capacity {
+            limits.order.remove(&slot.meta.order(capacity.policy));
+        }
+        self.bump_generation();
+        Some(slot.row)
+    }
+}
+#[cfg(feature = "hdv")]
+impl<R> Table<R>
+where
+    R: crate::hdv::HdvObject,
+{
+    /// Write a snapshot of all rows in the hdv binary format.
+    ///
+    /// Call it periodically on a fresh file and rename it over the previous one to keep the last state of the process for post-mortem queries.
+    pub fn dump_hdv(&self, mut writer: impl std::io::Write) -> anyhow::Result<()>
+    where
+        R::Object: hdv::serde::HdvSerialize,
+    {
+        // Encode into memory so that a slow writer does not hold off insertions
+        let mut buf = vec![];
+        {
+            let mut encoder = hdv::io::bin::HdvBinWriter::new(&mut buf);
+            let rows = self.rows.read().unwrap();
+            for (_k, slot) in rows.iter() {
+                encoder.write(slot.row.object())?;
+            }
+            encoder.flush()?;
+        }
+        writer.write_all(&buf)?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Read a snapshot written by [`Table::dump_hdv`] into a new table that can be queried with [`Table::to_view`].
+    pub fn load_hdv(reader: impl std::io::Read) -> anyhow::Result<Self>
+    where
+        R::Object: hdv::serde::HdvDeserialize,
+    {
+        let mut reader = hdv::io::bin::HdvBinReader::new(reader);
+        let table = Self::new();
+        loop {
+            match reader.read::<R::Object>() {
+                Ok(object) => {
+                    table.try_insert(R::from_object(object))?;
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => break,
+                Err(e) => return Err(e.into()),
+            }
+        }
+        Ok(table)
     }
 }
 impl<R> Default for Table<R> {
@@ -171,22 +709,394 @@ impl<R> Clone for Table<R> {
     fn clone(&self) -> Self {
         Self {
             rows: self.rows.clone(),
+            generation: self.generation.clone(),
+            cache: self.cache.clone(),
+            views: self.views.clone(),
+            default_view: self.default_view.clone(),
+            limits: self.limits.clone(),
         }
     }
 }
 
-fn infer_type(values: &[Value]) -> LiteralType {
-    for v in values {
-        match v {
-            Value::Bool(_) => return LiteralType::Bool,
-            Value::UInt(_) => return LiteralType::UInt,
-            Value::Int(_) => return LiteralType::Int,
-            Value::Float(_) => return LiteralType::Float,
-            Value::String(_) => return LiteralType::String,
-            Value::Bytes(_) | Value::List(_) | Value::Null => continue,
+/// A query parsed by [`Table::prepare`].
+///
+/// Unlike [`Table::to_view`], it always reads the current rows instead of going through the query cache.
+pub struct PreparedQuery<R> {
+    table: Table<R>,
+    sql: String,
+    /// Ends with `meta_columns`.
+    schema: Vec<(String, LiteralType)>,
+    meta_columns: Vec<MetaColumn>,
+    plan: QueryPlan,
+    statement: Statement,
+}
+impl<R: TableRow + ValueDisplay> PreparedQuery<R> {
+    pub fn sql(&self) -> &str {
+        &self.sql
+    }
+
+    pub fn to_view(&self) -> anyhow::Result<TableViewWrite> {
+        self.to_view_with(&ViewOptions::default())
+    }
+
+    /// Render with `options`, whose meta columns are those the query was prepared with if left empty.
+    pub fn to_view_with(&self, options: &ViewOptions) -> anyhow::Result<TableViewWrite> {
+        let mut prepared;
+        let options = if options.meta_columns() == self.meta_columns {
+            options
+        } else {
+            ensure!(
+                options.meta_columns.is_empty(),
+                "The query was prepared with other meta columns"
+            );
+            prepared = options.clone();
+            prepared.meta_columns = self.meta_columns.clone();
+            &prepared
+        };
+        let (_, view) = self.table.execute(
+            &self.schema,
+            &StaticAccess,
+            &self.plan,
+            &self.statement,
+            options,
+        )?;
+        Ok(view)
+    }
+}
+impl<R> fmt::Debug for PreparedQuery<R> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PreparedQuery")
+            .field("sql", &self.sql)
+            .finish()
+    }
+}
+
+/// Run the statement over an empty frame of the schema to surface unknown columns and type errors.
+fn check_statement(schema: &[(String, LiteralType)], statement: &Statement) -> anyhow::Result<()> {
+    let columns = schema
+        .iter()
+        .map(|(name, ty)| ColumnBuilder::new(*ty).build(name.clone()))
+        .collect();
+    let frame = Frame::new(columns)?;
+    let mut executor = dfsql::backend::DynamicExecutor::from_frame("table", frame);
+    statement(&mut executor)?;
+    executor.collect()?;
+    Ok(())
+}
+
+/// Read the values of rows in the order of the schema passed to [`Table::query`].
+pub(crate) trait RowAccess<R> {
+    fn visit_fields(&self, row: &R, visitor: &mut dyn FieldVisitor) -> anyhow::Result<()>;
+}
+
+/// Read rows under [`TableRow::schema`].
+struct StaticAccess;
+impl<R: TableRow> RowAccess<R> for StaticAccess {
+    fn visit_fields(&self, row: &R, visitor: &mut dyn FieldVisitor) -> anyhow::Result<()> {
+        row.try_visit_fields(visitor)
+    }
+}
+
+fn display_value<R: ValueDisplay>(
+    header: &str,
+    value: Option<LiteralValue>,
+    options: &ViewOptions,
+) -> String {
+    if let Some(LiteralValue::Float(v)) = value
+        && !v.is_finite()
+        && let Some(s) = options.non_finite.display()
+    {
+        return s;
+    }
+    if let Some(format) = options.column_formats.get(header)
+        && let Some(v) = &value
+        && let Some(s) = format.format(v)
+    {
+        return s;
+    }
+    if let Some(LiteralValue::Float(v)) = value
+        && v.is_finite()
+        && let Some(precision) = options.float_precision(header, R::custom_display(header))
+    {
+        return format::fixed(v, precision);
+    }
+    R::display_value(header, value)
+}
+
+/// Values of one row in the order of the schema.
+struct RowBuffer {
+    values: Vec<Option<LiteralValue>>,
+}
+impl FieldVisitor for RowBuffer {
+    fn visit(&mut self, index: usize, value: Option<LiteralValue>) {
+        self.values[index] = value;
+    }
+    fn visit_null(&mut self, index: usize) {
+        self.values[index] = None;
+    }
+    fn visit_str(&mut self, index: usize, value: &str) {
+        self.values[index] = Some(LiteralValue::String(value.into()));
+    }
+    fn visit_uint(&mut self, index: usize, value: u64) {
+        self.values[index] = Some(value.into());
+    }
+    fn visit_int(&mut self, index: usize, value: i64) {
+        self.values[index] = Some(value.into());
+    }
+    fn visit_float(&mut self, index: usize, value: f64) {
+        self.values[index] = Some(value.into());
+    }
+    fn visit_bool(&mut self, index: usize, value: bool) {
+        self.values[index] = Some(value.into());
+    }
+    fn visit_timestamp(&mut self, index: usize, value: SystemTime) {
+        self.values[index] = Some(value.into());
+    }
+    fn visit_duration(&mut self, index: usize, value: Duration) {
+        self.values[index] = Some(value.into());
+    }
+    fn visit_ip_addr(&mut self, index: usize, value: IpAddr) {
+        self.values[index] = Some(LiteralValue::IpAddr(value));
+    }
+    fn visit_socket_addr(&mut self, index: usize, value: SocketAddr) {
+        self.values[index] = Some(LiteralValue::SocketAddr(value));
+    }
+    fn visit_byte_size(&mut self, index: usize, value: u64) {
+        self.values[index] = Some(LiteralValue::ByteSize(value));
+    }
+    fn visit_bytes(&mut self, index: usize, value: &[u8]) {
+        self.values[index] = Some(LiteralValue::Bytes(value.into()));
+    }
+    fn visit_list(&mut self, index: usize, value: &[Option<LiteralValue>]) {
+        self.values[index] = Some(LiteralValue::List(value.into()));
+    }
+}
+
+struct ColumnBuilders {
+    columns: Vec<ColumnBuilder>,
+}
+impl FieldVisitor for ColumnBuilders {
+    fn visit_null(&mut self, index: usize) {
+        self.columns[index].push_null();
+    }
+    fn visit_str(&mut self, index: usize, value: &str) {
+        let ColumnBuilder::String(c) = &mut self.columns[index] else {
+            panic!("column {index} is not a string");
+        };
+        c.push(Some(value.to_string()));
+    }
+    fn visit_uint(&mut self, index: usize, value: u64) {
+        let ColumnBuilder::UInt(c) = &mut self.columns[index] else {
+            panic!("column {index} is not an unsigned integer");
+        };
+        c.push(Some(value));
+    }
+    fn visit_int(&mut self, index: usize, value: i64) {
+        let ColumnBuilder::Int(c) = &mut self.columns[index] else {
+            panic!("column {index} is not an integer");
+        };
+        c.push(Some(value));
+    }
+    fn visit_float(&mut self, index: usize, value: f64) {
+        let ColumnBuilder::Float(c) = &mut self.columns[index] else {
+            panic!("column {index} is not a float");
+        };
+        c.push(Some(value));
+    }
+    fn visit_bool(&mut self, index: usize, value: bool) {
+        let ColumnBuilder::Bool(c) = &mut self.columns[index] else {
+            panic!("column {index} is not a bool");
+        };
+        c.push(Some(value));
+    }
+    fn visit_bytes(&mut self, index: usize, value: &[u8]) {
+        let ColumnBuilder::Bytes(c) = &mut self.columns[index] else {
+            panic!("column {index} is not bytes");
+        };
+        c.push(Some(value.to_vec()));
+    }
+    fn visit_list(&mut self, index: usize, value: &[Option<LiteralValue>]) {
+        let ColumnBuilder::List(c) = &mut self.columns[index] else {
+            panic!("column {index} is not a list");
+        };
+        c.push(Some(value.iter().cloned().map(to_frame_value).collect()));
+    }
+}
+
+enum ColumnBuilder {
+    String(Vec<Option<String>>),
+    UInt(Vec<Option<u64>>),
+    Int(Vec<Option<i64>>),
+    Float(Vec<Option<f64>>),
+    Bool(Vec<Option<bool>>),
+    Bytes(Vec<Option<Vec<u8>>>),
+    List(Vec<Option<Vec<Value>>>),
+}
+impl ColumnBuilder {
+    fn new(ty: LiteralType) -> Self {
+        match frame_type(ty) {
+            LiteralType::String => Self::String(vec![]),
+            LiteralType::UInt => Self::UInt(vec![]),
+            LiteralType::Int => Self::Int(vec![]),
+            LiteralType::Float => Self::Float(vec![]),
+            LiteralType::Bool => Self::Bool(vec![]),
+            LiteralType::Bytes => Self::Bytes(vec![]),
+            LiteralType::List => Self::List(vec![]),
+            LiteralType::Timestamp
+            | LiteralType::Duration
+            | LiteralType::IpAddr
+            | LiteralType::SocketAddr
+            | LiteralType::ByteSize => unreachable!(),
+        }
+    }
+
+    fn push_null(&mut self) {
+        match self {
+            Self::String(c) => c.push(None),
+            Self::UInt(c) => c.push(None),
+            Self::Int(c) => c.push(None),
+            Self::Float(c) => c.push(None),
+            Self::Bool(c) => c.push(None),
+            Self::Bytes(c) => c.push(None),
+            Self::List(c) => c.push(None),
+        }
+    }
+
+    fn build(self, header: String) -> Column {
+        match self {
+            Self::String(c) => Column::new(header, c),
+            Self::UInt(c) => Column::new(header, c),
+            Self::Int(c) => Column::new(header, c),
+            Self::Float(c) => Column::new(header, c),
+            Self::Bool(c) => Column::new(header, c),
+            Self::Bytes(c) => Column::new(header, c),
+            Self::List(c) => Column::new(header, c),
         }
     }
-    LiteralType::String
+}
+
+/// Prefer the type of the source column of the same name so that the type does not depend on the data.
+///
+/// The values only decide the type of computed columns or of source columns whose type was changed by the query.
+fn output_type(source: Option<LiteralType>, values: &[Value]) -> LiteralType {
+    match (source, infer_type(values)) {
+        (Some(source), None) => source,
+        (Some(source), Some(inferred)) if frame_type(source) == inferred => source,
+        (_, Some(inferred)) => inferred,
+        (None, None) => LiteralType::String,
+    }
+}
+
+/// Return `None` if all values are null.
+fn infer_type(values: &[Value]) -> Option<LiteralType> {
+    for v in values {
+        let t = match v {
+            Value::Bool(_) => LiteralType::Bool,
+            Value::UInt(_) => LiteralType::UInt,
+            Value::Int(_) => LiteralType::Int,
+            Value::Float(_) => LiteralType::Float,
+            Value::String(_) => LiteralType::String,
+            Value::Bytes(_) => LiteralType::Bytes,
+            Value::List(_) => LiteralType::List,
+            Value::Null => continue,
+        };
+        return Some(t);
+    }
+    None
+}
+
+/// Return the type the frame stores the values of `ty` as.
+fn frame_type(ty: LiteralType) -> LiteralType {
+    match ty {
+        LiteralType::Timestamp | LiteralType::Duration => LiteralType::Float,
+        LiteralType::IpAddr | LiteralType::SocketAddr => LiteralType::String,
+        LiteralType::ByteSize => LiteralType::UInt,
+        LiteralType::String
+        | LiteralType::UInt
+        | LiteralType::Int
+        | LiteralType::Float
+        | LiteralType::Bool
+        | LiteralType::Bytes
+        | LiteralType::List => ty,
+    }
+}
+
+/// Decode a frame value back into `ty`, keeping the primitive value if it does not fit.
+fn from_frame_value(value: Value, ty: LiteralType) -> Option<LiteralValue> {
+    let value = match value {
+        Value::Null => return None,
+        Value::Bool(b) => b.into(),
+        Value::UInt(u) => match ty {
+            LiteralType::ByteSize => LiteralValue::ByteSize(u),
+            _ => u.into(),
+        },
+        Value::Int(i) => i.into(),
+        Value::Float(f) => match ty {
+            LiteralType::Timestamp => match timestamp_from_secs(f) {
+                Some(t) => t.into(),
+                None => f.into(),
+            },
+            LiteralType::Duration => match Duration::try_from_secs_f64(f) {
+                Ok(d) => d.into(),
+                Err(_) => f.into(),
+            },
+            _ => f.into(),
+        },
+        Value::String(s) => match ty {
+            LiteralType::IpAddr => match s.parse::<IpAddr>() {
+                Ok(a) => a.into(),
+                Err(_) => LiteralValue::String(s),
+            },
+            LiteralType::SocketAddr => match s.parse::<SocketAddr>() {
+                Ok(a) => a.into(),
+                Err(_) => LiteralValue::String(s),
+            },
+            _ => LiteralValue::String(s),
+        },
+        Value::Bytes(b) => LiteralValue::Bytes(b[..].into()),
+        Value::List(l) => LiteralValue::List(
+            l.iter()
+                .map(|v| from_frame_value(v.clone(), LiteralType::String))
+                .collect(),
+        ),
+    };
+    Some(value)
+}
+
+/// Encode a value the way [`ColumnBuilders`] stores it in the frame.
+fn to_frame_value(value: Option<LiteralValue>) -> Value {
+    let Some(value) = value else {
+        return Value::Null;
+    };
+    match value {
+        LiteralValue::String(v) => Value::String(v),
+        LiteralValue::UInt(v) | LiteralValue::ByteSize(v) => Value::UInt(v),
+        LiteralValue::Int(v) => Value::Int(v),
+        LiteralValue::Float(v) => Value::Float(v),
+        LiteralValue::Bool(v) => Value::Bool(v),
+        LiteralValue::Timestamp(v) => Value::Float(timestamp_to_secs(v)),
+        LiteralValue::Duration(v) => Value::Float(v.as_secs_f64()),
+        LiteralValue::IpAddr(v) => Value::String(v.to_string().into()),
+        LiteralValue::SocketAddr(v) => Value::String(v.to_string().into()),
+        LiteralValue::Bytes(v) => Value::Bytes(v[..].into()),
+        LiteralValue::List(v) => Value::List(v.iter().cloned().map(to_frame_value).collect()),
+    }
+}
+
+pub(crate) fn timestamp_to_secs(value: SystemTime) -> f64 {
+    match value.duration_since(UNIX_EPOCH) {
+        Ok(d) => d.as_secs_f64(),
+        Err(e) => -e.duration().as_secs_f64(),
+    }
+}
+
+fn timestamp_from_secs(secs: f64) -> Option<SystemTime> {
+    let since_epoch = Duration::try_from_secs_f64(secs.abs()).ok()?;
+    if secs < 0. {
+        UNIX_EPOCH.checked_sub(since_epoch)
+    } else {
+        UNIX_EPOCH.checked_add(since_epoch)
+    }
 }
 
 fn alignment(value: LiteralType) -> Alignment {
@@ -196,6 +1106,13 @@ fn alignment(value: LiteralType) -> Alignment {
         LiteralType::Int => Alignment::Right,
         LiteralType::Float => Alignment::Right,
         LiteralType::Bool => Alignment::Right,
+        LiteralType::Timestamp => Alignment::Right,
+        LiteralType::Duration => Alignment::Right,
+        LiteralType::IpAddr => Alignment::Left,
+        LiteralType::SocketAddr => Alignment::Left,
+        LiteralType::ByteSize => Alignment::Right,
+        LiteralType::Bytes => Alignment::Left,
+        LiteralType::List => Alignment::Left,
     }
 }
 
@@ -205,7 +1122,12 @@ pub struct RowGuard<'table, R> {
     key: RowKey,
 }
 impl<R> RowGuard<'_, R> {
-    pub fn inspect_mut(&self, f: fn(&mut R)) {
+    pub fn key(&self) -> RowKey {
+        self.key
+    }
+
+    /// Return `None` without calling `f` if the row was evicted.
+    pub fn inspect_mut<T>(&self, f: impl FnOnce(&mut R) -> T) -> Option<T> {
         inspect_mut(self.table, self.key, f)
     }
 }
@@ -221,7 +1143,12 @@ pub struct RowOwnedGuard<R> {
     key: RowKey,
 }
 impl<R> RowOwnedGuard<R> {
-    pub fn inspect_mut(&self, f: fn(&mut R)) {
+    pub fn key(&self) -> RowKey {
+        self.key
+    }
+
+    /// Return `None` without calling `f` if the row was evicted.
+    pub fn inspect_mut<T>(&self, f: impl FnOnce(&mut R) -> T) -> Option<T> {
         inspect_mut(&self.table, self.key, f)
     }
 }
@@ -231,12 +1158,128 @@ impl<R> Drop for RowOwnedGuard<R> {
     }
 }
 
-fn inspect_mut<R>(table: &Table<R>, key: RowKey, f: fn(&mut R)) {
+fn inspect_mut<R, T>(table: &Table<R>, key: RowKey, f: impl FnOnce(&mut R) -> T) -> Option<T> {
     let mut map = table.rows.write().unwrap();
-    let Some(session) = map.get_mut(key) else {
-        return;
-    };
-    f(session)
+    let slot = map.get_mut(key)?;
+    let generation = table.bump_generation();
+    let mut limits = table.limits.lock().unwrap();
+    if let Some(capacity) = limits.capacity
+        && capacity.policy == CapacityPolicy::EvictLeastRecentlyUpdated
+    {
+        limits.order.remove(&slot.meta.updated);
+        limits.order.insert(generation, key);
+    }
+    drop(limits);
+    slot.meta.update(generation);
+    Some(f(&mut slot.row))
+}
+
+/// Scale a row count of a sample up to the whole table.
+fn scale_count(value: &mut LiteralValue, scale: f64) {
+    match value {
+        LiteralValue::UInt(v) => *v = (*v as f64 * scale).round() as u64,
+        LiteralValue::Int(v) => *v = (*v as f64 * scale).round() as i64,
+        LiteralValue::Float(v) => *v *= scale,
+        _ => (),
+    }
+}
+
+/// Evict rows until one more fits.
+fn evict<R>(map: &mut SlotMap<RowKey, Slot<R>>, limits: &mut Limits, capacity: Capacity) {
+    while capacity.max_rows <= map.len() {
+        let Some((_, victim)) = limits.order.pop_first() else {
+            return;
+        };
+        map.remove(victim);
+        limits.evicted += 1;
+    }
+}
+
+#[derive(Debug)]
+struct Slot<R> {
+    row: R,
+    meta: RowMeta,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct RowMeta {
+    /// Generation of the insertion, which orders rows even if the clock steps back.
+    inserted: u64,
+    /// Generation of the insertion or last mutation.
+    updated: u64,
+    inserted_at: SystemTime,
+    updated_at: SystemTime,
+    /// Monotonic twins of the timestamps for `_age` and `_idle`, which must not jump with the wall clock.
+    inserted_instant: Instant,
+    updated_instant: Instant,
+}
+impl RowMeta {
+    fn new(generation: u64) -> Self {
+        let now = SystemTime::now();
+        let instant = Instant::now();
+        Self {
+            inserted: generation,
+            updated: generation,
+            inserted_at: now,
+            updated_at: now,
+            inserted_instant: instant,
+            updated_instant: instant,
+        }
+    }
+
+    fn update(&mut self, generation: u64) {
+        self.updated = generation;
+        self.updated_at = SystemTime::now();
+        self.updated_instant = Instant::now();
+    }
+
+    /// Return the generation that orders the row for eviction under `policy`.
+    fn order(&self, policy: CapacityPolicy) -> u64 {
+        match policy {
+            CapacityPolicy::EvictOldest => self.inserted,
+            CapacityPolicy::EvictLeastRecentlyUpdated => self.updated,
+        }
+    }
+}
+
+fn meta_value(column: MetaColumn, key: RowKey, meta: &RowMeta, now: Instant) -> LiteralValue {
+    match column {
+        MetaColumn::Key => key.id().into(),
+        MetaColumn::InsertedAt => meta.inserted_at.into(),
+        MetaColumn::UpdatedAt => meta.updated_at.into(),
+        MetaColumn::Age => now.duration_since(meta.inserted_instant).into(),
+        MetaColumn::Idle => now.duration_since(meta.updated_instant).into(),
+    }
+}
+
+fn with_meta_columns(
+    schema: &[(String, LiteralType)],
+    meta_columns: &[MetaColumn],
+) -> anyhow::Result<Vec<(String, LiteralType)>> {
+    for m in meta_columns {
+        ensure!(
+            !schema.iter().any(|(name, _)| name == m.name()),
+            "The meta column `{}` has the name of a column of the table",
+            m.name()
+        );
+    }
+    let meta = meta_columns
+        .iter()
+        .map(|m| (m.name().to_string(), m.literal_type()));
+    Ok(schema.iter().cloned().chain(meta).collect())
 }
 
 new_key_type! { pub struct RowKey; }
+impl RowKey {
+    /// Return the number shown in the `_key` column.
+    pub fn id(&self) -> u64 {
+        self.0.as_ffi()
+    }
+
+    /// Return the key of the id shown in the `_key` column.
+    ///
+    /// An id that never belonged to the table yields a key no row is found under.
+    pub fn from_id(id: u64) -> Self {
+        KeyData::from_ffi(id).into()
+    }
+}
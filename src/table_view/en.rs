@@ -1,51 +1,197 @@
 use core::fmt;
-use std::sync::Arc;
+use std::{
+    collections::HashSet,
+    io::{self, IsTerminal},
+    sync::Arc,
+};
 
 use super::TableView;
+use crate::{
+    sample::SampleSize,
+    style::{ColorChoice, RESET, Style, visible_len},
+};
 
+#[derive(Debug, Clone)]
 pub struct TableViewWrite {
     t: TableView,
     alignments: Arc<[Alignment]>,
+    /// Style of every cell; `None` renders plain text.
+    styles: Option<Arc<[Arc<[Style]>]>>,
+    /// Whether to render `styles`.
+    color: ColorChoice,
+    /// Rows the view was computed from if they are a sample of the table.
+    sample: Option<SampleSize>,
 }
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
 pub enum Alignment {
     Left,
     Right,
+    /// Extra padding goes to the right if it cannot be split evenly.
+    Center,
 }
 impl TableViewWrite {
     pub fn new(t: TableView, alignments: Arc<[Alignment]>) -> Option<Self> {
         if t.titles.len() != alignments.len() {
             return None;
         }
-        Some(Self { t, alignments })
+        Some(Self {
+            t,
+            alignments,
+            styles: None,
+            color: ColorChoice::Always,
+            sample: None,
+        })
+    }
+
+    pub fn alignments(&self) -> &[Alignment] {
+        &self.alignments
+    }
+
+    /// Style every cell with ANSI escapes; return `false` if the shape does not match the rows.
+    pub fn set_styles(&mut self, styles: Vec<Vec<Style>>) -> bool {
+        let matches = styles.len() == self.t.rows.len()
+            && styles.iter().all(|r| r.len() == self.t.titles.len());
+        if !matches {
+            return false;
+        }
+        self.styles = Some(styles.into_iter().map(|r| r.into()).collect());
+        true
+    }
+
+    pub fn clear_styles(&mut self) {
+        self.styles = None;
+    }
+
+    /// Choose whether the styles are rendered; [`ColorChoice::Always`] unless set.
+    pub fn set_color(&mut self, color: ColorChoice) {
+        self.color = color;
+    }
+
+    /// Write the view, rendering the styles by [`ColorChoice`] for whether `writer` is a terminal.
+    ///
+    /// A view computed from a sample is followed by a footer telling the sample size.
+    pub fn write_to<W: io::Write + IsTerminal>(&self, mut writer: W) -> io::Result<()> {
+        let terminal = writer.is_terminal();
+        write!(writer, "{}", self.display_for(terminal))?;
+        if let Some(sample) = self.sample {
+            writeln!(writer, "{sample}")?;
+        }
+        Ok(())
+    }
+
+    /// Display the view, rendering the styles by [`ColorChoice`] for a destination that is a terminal or not.
+    pub fn display_for(&self, terminal: bool) -> impl fmt::Display + '_ {
+        Rendered {
+            view: self,
+            styled: self.color.enabled(terminal),
+        }
+    }
+
+    pub fn sample(&self) -> Option<SampleSize> {
+        self.sample
+    }
+
+    /// Mark the view as computed from a sample; [`TableViewWrite::write_to`] then ends it with a footer telling the sample size.
+    pub fn set_sample(&mut self, sample: SampleSize) {
+        self.sample = Some(sample);
+    }
+
+    /// Return `false` if there is no column titled `column`.
+    pub fn set_alignment(&mut self, column: &str, alignment: Alignment) -> bool {
+        let Some(i) = self.t.titles.iter().position(|t| &**t == column) else {
+            return false;
+        };
+        let mut alignments = self.alignments.to_vec();
+        alignments[i] = alignment;
+        self.alignments = alignments.into();
+        true
+    }
+
+    /// Return a view with the columns in `order` first, the other columns after them in their current order, and the `hidden` columns left out.
+    pub fn arrange(&self, order: &[String], hidden: &HashSet<String>) -> Self {
+        let indices = column_order(&self.t.titles, order, hidden);
+        let titles = indices.iter().map(|&i| self.t.titles[i].clone()).collect();
+        let rows = self
+            .t
+            .rows
+            .iter()
+            .map(|r| indices.iter().map(|&i| r[i].clone()).collect())
+            .collect();
+        let alignments = indices.iter().map(|&i| self.alignments[i]).collect();
+        let styles = self.styles.as_ref().map(|styles| {
+            styles
+                .iter()
+                .map(|r| indices.iter().map(|&i| r[i]).collect())
+                .collect()
+        });
+        Self {
+            t: TableView { titles, rows },
+            alignments,
+            styles,
+            color: self.color,
+            sample: self.sample,
+        }
     }
 }
 
+/// Return the indices of the visible `titles` in display order.
+pub(crate) fn column_order(
+    titles: &[impl AsRef<str>],
+    order: &[String],
+    hidden: &HashSet<String>,
+) -> Vec<usize> {
+    let position = |name: &str| titles.iter().position(|t| t.as_ref() == name);
+    let ordered = order.iter().filter_map(|name| position(name));
+    let rest = (0..titles.len()).filter(|&i| !order.iter().any(|name| name == titles[i].as_ref()));
+    ordered
+        .chain(rest)
+        .filter(|&i| !hidden.contains(titles[i].as_ref()))
+        .collect()
+}
+
 impl fmt::Display for TableViewWrite {
+    /// Render the styles only for [`ColorChoice::Always`].
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.display_for(false).fmt(f)
+    }
+}
+
+struct Rendered<'a> {
+    view: &'a TableViewWrite,
+    styled: bool,
+}
+impl fmt::Display for Rendered<'_> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let mut column_lengths = self
+        let this = self.view;
+        let mut column_lengths = this
             .t
             .titles
             .iter()
-            .map(|t| t.len())
+            .map(|t| visible_len(t))
             .collect::<Vec<usize>>();
-        for r in self.t.rows.iter() {
+        for r in this.t.rows.iter() {
             for (i, c) in r.iter().enumerate() {
-                column_lengths[i] = column_lengths[i].max(c.len());
+                column_lengths[i] = column_lengths[i].max(visible_len(c));
             }
         }
 
-        for (t, len) in self.t.titles.iter().zip(column_lengths.iter()) {
-            write(f, t, Alignment::Left, *len)?;
+        let plain = Style::default();
+        for (t, len) in this.t.titles.iter().zip(column_lengths.iter()) {
+            write(f, t, Alignment::Left, *len, plain)?;
         }
         writeln!(f)?;
-        for r in self.t.rows.iter() {
-            for ((c, len), a) in r
+        let styles = this.styles.as_ref().filter(|_| self.styled);
+        for (row, r) in this.t.rows.iter().enumerate() {
+            for (i, ((c, len), a)) in r
                 .iter()
                 .zip(column_lengths.iter())
-                .zip(self.alignments.iter())
+                .zip(this.alignments.iter())
+                .enumerate()
             {
-                write(f, c, *a, *len)?;
+                let style = styles.map(|s| s[row][i]).unwrap_or(plain);
+                write(f, c, *a, *len, style)?;
             }
             writeln!(f)?;
         }
@@ -53,21 +199,29 @@ impl fmt::Display for TableViewWrite {
     }
 }
 
-fn write(f: &mut fmt::Formatter<'_>, s: &str, a: Alignment, len: usize) -> fmt::Result {
-    let padding = len - s.len();
-    match a {
-        Alignment::Left => {
-            write!(f, "{s}")?;
-            for _ in 0..padding {
-                write!(f, " ")?;
-            }
-        }
-        Alignment::Right => {
-            for _ in 0..padding {
-                write!(f, " ")?;
-            }
-            write!(f, "{s}")?;
-        }
+fn write(
+    f: &mut fmt::Formatter<'_>,
+    s: &str,
+    a: Alignment,
+    len: usize,
+    style: Style,
+) -> fmt::Result {
+    let padding = len - visible_len(s);
+    let left = match a {
+        Alignment::Left => 0,
+        Alignment::Right => padding,
+        Alignment::Center => padding / 2,
+    };
+    for _ in 0..left {
+        write!(f, " ")?;
+    }
+    if style.is_plain() {
+        write!(f, "{s}")?;
+    } else {
+        write!(f, "{}{s}{RESET}", style.escape())?;
+    }
+    for _ in left..padding {
+        write!(f, " ")?;
     }
     write!(f, " ")?;
     Ok(())
@@ -99,6 +253,38 @@ mod tests {
             "id  usage 
 cpu 80    
 mem 20    
+"
+        )
+    }
+
+    #[test]
+    fn test_arrange() {
+        let titles = vec!["id", "usage", "unit"];
+        let rows = vec![
+            vec!["cpu", "80", "%"], //
+            vec!["mem", "2", "GiB"],
+        ];
+        let t = TableView {
+            titles: titles.into_iter().map(|t| t.into()).collect(),
+            rows: rows
+                .into_iter()
+                .map(|r| r.into_iter().map(|c| c.into()).collect())
+                .collect(),
+        };
+        let mut t = TableViewWrite::new(
+            t,
+            [Alignment::Left, Alignment::Right, Alignment::Left].into(),
+        )
+        .unwrap();
+        assert!(t.set_alignment("unit", Alignment::Center));
+        assert!(!t.set_alignment("x", Alignment::Center));
+        let t = t.arrange(&["unit".to_string()], &["id".to_string()].into());
+        assert_eq!(t.alignments(), [Alignment::Center, Alignment::Right]);
+        assert_eq!(
+            t.to_string(),
+            "unit usage 
+ %      80 
+GiB      2 
 "
         )
     }
@@ -0,0 +1,197 @@
+use core::{fmt, ops::Range};
+
+use crate::{
+    lex::{KEYWORDS, STATEMENTS, TokenKind, lex},
+    row::LiteralType,
+};
+
+/// Unknown columns found in a query that dfsql failed to run.
+///
+/// It is attached as the context of the dfsql error and can be retrieved with [`anyhow::Error::downcast_ref`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ValidationError {
+    pub sql: String,
+    pub unknown_columns: Vec<UnknownColumn>,
+    /// Columns of the schema the query ran against.
+    pub available: Vec<String>,
+}
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for column in &self.unknown_columns {
+            write!(f, "unknown column `{}`", column.name)?;
+            if let Some(suggestion) = &column.suggestion {
+                write!(f, ", did you mean `{suggestion}`?")?;
+            }
+            writeln!(f)?;
+            let line_start = self.sql[..column.span.start]
+                .rfind('\n')
+                .map(|i| i + 1)
+                .unwrap_or(0);
+            let line_end = self.sql[line_start..]
+                .find('\n')
+                .map(|i| line_start + i)
+                .unwrap_or(self.sql.len());
+            let indent = self.sql[line_start..column.span.start].chars().count();
+            let width = self.sql[column.span.clone()].chars().count();
+            writeln!(f, "  {}", &self.sql[line_start..line_end])?;
+            writeln!(f, "  {}{}", " ".repeat(indent), "^".repeat(width))?;
+        }
+        write!(f, "available columns: {}", self.available.join(", "))
+    }
+}
+impl std::error::Error for ValidationError {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnknownColumn {
+    pub name: String,
+    /// Byte range of the name in the query string.
+    pub span: Range<usize>,
+    /// The closest column of the schema, if any is close enough.
+    pub suggestion: Option<String>,
+}
+
+/// Attach a [`ValidationError`] to `error` if the query references columns missing from `schema`.
+pub(crate) fn explain(
+    error: anyhow::Error,
+    sql: &str,
+    schema: &[(String, LiteralType)],
+) -> anyhow::Error {
+    match diagnose(sql, schema) {
+        Some(validation) => error.context(validation),
+        None => error,
+    }
+}
+
+/// Collect identifiers that are neither keywords, function calls, names introduced by the query nor columns of `schema`.
+///
+/// The lexer does not know the full grammar, so the result is only meant to explain an error dfsql already reported.
+pub(crate) fn diagnose(sql: &str, schema: &[(String, LiteralType)]) -> Option<ValidationError> {
+    let tokens = lex(sql);
+    let mut introduced = vec![];
+    for (i, token) in tokens.iter().enumerate() {
+        let TokenKind::Ident(word) = token.kind else {
+            continue;
+        };
+        let new_name = match word {
+            "alias" | "as" => tokens.get(i + 1),
+            "rename" => tokens.get(i + 2),
+            _ => None,
+        };
+        if let Some(new_name) = new_name {
+            match &new_name.kind {
+                TokenKind::Ident(name) => introduced.push(name.to_string()),
+                TokenKind::String(name) => introduced.push(name.clone()),
+                _ => (),
+            }
+        }
+    }
+
+    let mut unknown_columns = vec![];
+    for (i, token) in tokens.iter().enumerate() {
+        let TokenKind::Ident(name) = token.kind else {
+            continue;
+        };
+        let is_call = tokens.get(i + 1).is_some_and(|next| {
+            next.kind == TokenKind::Other('(') && next.span.start == token.span.end
+        });
+        let known = STATEMENTS.contains(&name)
+            || KEYWORDS.contains(&name)
+            || schema.iter().any(|(column, _)| column == name)
+            || introduced.iter().any(|column| column == name);
+        if is_call || known {
+            continue;
+        }
+        unknown_columns.push(UnknownColumn {
+            name: name.to_string(),
+            span: token.span.clone(),
+            suggestion: suggest(name, schema.iter().map(|(column, _)| column.as_str())),
+        });
+    }
+    if unknown_columns.is_empty() {
+        return None;
+    }
+    Some(ValidationError {
+        sql: sql.to_string(),
+        unknown_columns,
+        available: schema.iter().map(|(column, _)| column.clone()).collect(),
+    })
+}
+
+/// Return the candidate with the smallest edit distance if it is small relative to the length of `name`.
+pub(crate) fn suggest<'a>(name: &str, candidates: impl Iterator<Item = &'a str>) -> Option<String> {
+    let max_distance = (name.chars().count() / 3).max(1);
+    candidates
+        .map(|candidate| {
+            let distance = if candidate.eq_ignore_ascii_case(name) {
+                0
+            } else {
+                edit_distance(name, candidate)
+            };
+            (distance, candidate)
+        })
+        .filter(|(distance, _)| *distance <= max_distance)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, candidate)| candidate.to_string())
+}
+
+/// Levenshtein distance counting transposed neighbors as a single edit.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    // d[i][j] is the distance between a[..i] and b[..j]
+    let mut d = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in d.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, cell) in d[0].iter_mut().enumerate() {
+        *cell = j;
+    }
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            let mut distance = (d[i - 1][j] + 1)
+                .min(d[i][j - 1] + 1)
+                .min(d[i - 1][j - 1] + cost);
+            if 1 < i && 1 < j && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                distance = distance.min(d[i - 2][j - 2] + 1);
+            }
+            d[i][j] = distance;
+        }
+    }
+    d[a.len()][b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_diagnose() {
+        let schema = [
+            ("cpu_usage".to_string(), LiteralType::Float),
+            ("mem".to_string(), LiteralType::UInt),
+        ];
+        let sql = "select mem cpu_usage\nfilter cpu_usgae > 5";
+        let error = diagnose(sql, &schema).unwrap();
+        assert_eq!(
+            error.unknown_columns,
+            [UnknownColumn {
+                name: "cpu_usgae".to_string(),
+                span: 28..37,
+                suggestion: Some("cpu_usage".to_string()),
+            }]
+        );
+        assert_eq!(
+            error.to_string(),
+            "unknown column `cpu_usgae`, did you mean `cpu_usage`?
+  filter cpu_usgae > 5
+         ^^^^^^^^^
+available columns: cpu_usage, mem"
+        );
+
+        assert!(diagnose("sort mem desc\nlimit 3", &schema).is_none());
+        assert!(diagnose("select mem alias m\nsort m", &schema).is_none());
+        let error = diagnose("sort zzz", &schema).unwrap();
+        assert_eq!(error.unknown_columns[0].suggestion, None);
+    }
+}